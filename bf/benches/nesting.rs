@@ -0,0 +1,137 @@
+//! Compares the flat bytecode executor with the tree walking one it replaced
+//! on deeply nested loops.
+//!
+//! Run with `cargo bench -p bf`. The old parser and executor are kept below,
+//! stripped of everything the programs here do not use, so the comparison
+//! does not depend on a checkout of the previous version.
+
+extern crate bf;
+
+use bf::{compile, lex, optimize, parse, run, Action, CellChange, Config, ExecutionController, OpCode, Optimizations, Span, Tape};
+
+use std::sync::mpsc::{self, Sender};
+use std::time::{Duration, Instant};
+
+enum OldInstruction {
+    IncrementPointer,
+    DecrementPointer,
+    Increment,
+    Decrement,
+    Loop(Vec<(OldInstruction, usize)>),
+}
+
+//the previous parser, which parses the body of every loop again for each
+//loop around it
+fn old_parse(opcodes: Vec<(OpCode, usize)>) -> Vec<(OldInstruction, usize)> {
+    let mut program = Vec::new();
+    let mut loop_stack = 0;
+    let mut loop_start = 0;
+
+    for (i, op) in opcodes.iter().enumerate() {
+        if loop_stack == 0 {
+            let instr = match op.0 {
+                OpCode::IncrementPointer => Some(OldInstruction::IncrementPointer),
+                OpCode::DecrementPointer => Some(OldInstruction::DecrementPointer),
+                OpCode::Increment => Some(OldInstruction::Increment),
+                OpCode::Decrement => Some(OldInstruction::Decrement),
+                OpCode::LoopBegin => {
+                    loop_start = i;
+                    loop_stack += 1;
+                    None
+                },
+                _ => unreachable!()
+            };
+
+            if let Some(instr) = instr {
+                program.push((instr, op.1));
+            }
+        }
+        else {
+            match op.0 {
+                OpCode::LoopBegin => loop_stack += 1,
+                OpCode::LoopEnd => {
+                    loop_stack -= 1;
+                    if loop_stack == 0 {
+                        program.push((OldInstruction::Loop(old_parse(opcodes[loop_start+1..i].to_vec())), i));
+                    }
+                },
+                _ => (),
+            }
+        }
+    }
+
+    program
+}
+
+//a plain wrapping tape of bytes for the old executor, which does less work
+//than bf::Tape and so only makes the old executor look better
+struct OldTape {
+    cells: Vec<i64>,
+    pointer: usize,
+}
+
+//the previous executor, which calls itself for every loop iteration with a
+//new clone of the sender
+fn old_run(instructions: &[(OldInstruction, usize)], tape: &mut OldTape, send_cell: Sender<CellChange>, controller: &ExecutionController) {
+    for (instr, i) in instructions {
+        //cheaper than the pause and delay checks run makes
+        if controller.is_cancelled() {
+            break;
+        }
+
+        match instr {
+            OldInstruction::IncrementPointer => tape.pointer += 1,
+            OldInstruction::DecrementPointer => tape.pointer -= 1,
+            OldInstruction::Increment => tape.cells[tape.pointer] = (tape.cells[tape.pointer] + 1).rem_euclid(256),
+            OldInstruction::Decrement => tape.cells[tape.pointer] = (tape.cells[tape.pointer] - 1).rem_euclid(256),
+            OldInstruction::Loop(body) => {
+                while tape.cells[tape.pointer] != 0 {
+                    old_run(body, tape, send_cell.clone(), controller);
+                    if controller.is_cancelled() {
+                        break;
+                    }
+                }
+                continue;
+            }
+        }
+
+        send_cell.send(CellChange{index: tape.pointer as isize, content: tape.cells[tape.pointer], action: Action::Tape, span: Span::at(*i)}).unwrap();
+    }
+}
+
+//255 times over, sets a cell to 1 and clears it again from inside depth loops
+fn nested_program(depth: usize) -> String {
+    format!("-[>+{}-{}<-]", "[".repeat(depth), "]".repeat(depth))
+}
+
+fn time<T>(work: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = work();
+    (result, start.elapsed())
+}
+
+fn main() {
+    println!("{:>6} {:>12} {:>12} {:>12} {:>12}", "depth", "old parse", "new parse", "old run", "new run");
+
+    for &depth in &[250, 500, 1000, 2000] {
+        let source = nested_program(depth);
+        let config = Config::default();
+
+        let (old_program, old_parse_time) = time(|| old_parse(lex(&source)));
+        let (new_program, new_parse_time) = time(|| compile(&optimize(&parse(lex(&source)), &Optimizations::none())));
+
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let mut old_tape = OldTape{cells: vec![0; config.tape_length], pointer: 0};
+        let (_, old_run_time) = time(|| old_run(&old_program, &mut old_tape, send_cell.clone(), &ExecutionController::new()));
+
+        let mut new_tape = Tape::new(&config);
+        let (_, new_run_time) = time(|| run(&new_program, &mut new_tape, send_cell, &receive_data, &ExecutionController::new()));
+
+        assert_eq!(old_tape.cells, new_tape.cells());
+        drop(receive_cell);
+
+        println!("{:>6} {:>12.2?} {:>12.2?} {:>12.2?} {:>12.2?}", depth, old_parse_time, new_parse_time, old_run_time, new_run_time);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::optimizer::{Idiom, Node, Op};
+use crate::span::Span;
+
+/// An instruction of the flat program. Loops are jumps whose targets are
+/// worked out by `compile`, so running a program never searches for the
+/// matching bracket.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum Bytecode {
+    Add(i64),
+    Move(isize),
+    Write,
+    Read,
+    /// `[`: goes to the given position, just past the matching `]`, if the
+    /// current cell is 0.
+    JumpIfZero(usize),
+    /// `]`: goes back to the given position, the first instruction of the
+    /// loop body, unless the current cell is 0.
+    JumpUnlessZero(usize),
+    /// A recognised loop, followed by the loop itself for when the idiom
+    /// cannot be used. The position is just past that loop.
+    Idiom(Idiom, usize),
+    /// A `#`, which does nothing unless the controller has a breakpoint on
+    /// it.
+    Breakpoint,
+    Error(String),
+}
+
+/// A compiled program, with the text offsets every instruction came from.
+#[derive(Debug)]
+#[derive(Clone, PartialEq, Default)]
+pub struct Program {
+    pub code: Vec<Bytecode>,
+    pub offsets: Vec<Vec<usize>>,
+}
+
+impl Program {
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+
+    /// From the first to the last character of the instruction at
+    /// `position`.
+    pub fn span(&self, position: usize) -> Span {
+        let offsets = &self.offsets[position];
+
+        match (offsets.first(), offsets.last()) {
+            (Some(&first), Some(&last)) => Span::new(first, last + 1),
+            _ => Span::default()
+        }
+    }
+
+    fn push(&mut self, code: Bytecode, offsets: Vec<usize>) -> usize {
+        self.code.push(code);
+        self.offsets.push(offsets);
+        self.code.len() - 1
+    }
+}
+
+/// Flattens the optimized program into bytecode.
+pub fn compile(nodes: &[Node]) -> Program {
+    let mut program = Program::default();
+    compile_into(nodes, &mut program);
+    program
+}
+
+fn compile_into(nodes: &[Node], program: &mut Program) {
+    for node in nodes {
+        match &node.op {
+            Op::Add(amount) => {
+                program.push(Bytecode::Add(*amount), node.offsets.clone());
+            },
+            Op::Move(amount) => {
+                program.push(Bytecode::Move(*amount), node.offsets.clone());
+            },
+            Op::Write => {
+                program.push(Bytecode::Write, node.offsets.clone());
+            },
+            Op::Read => {
+                program.push(Bytecode::Read, node.offsets.clone());
+            },
+            Op::Loop(body) => {
+                compile_loop(body, node.span(), program);
+            },
+            Op::Idiom(idiom, body) => {
+                let position = program.push(Bytecode::Idiom(idiom.clone(), 0), node.offsets.clone());
+
+                //the brackets are the first and last offsets of an idiom
+                let after = compile_loop(body, node.span(), program);
+                program.code[position] = Bytecode::Idiom(idiom.clone(), after);
+            },
+            Op::Breakpoint => {
+                program.push(Bytecode::Breakpoint, node.offsets.clone());
+            },
+            Op::Error(e) => {
+                program.push(Bytecode::Error(e.clone()), node.offsets.clone());
+            }
+        }
+    }
+}
+
+//returns the position just past the loop
+fn compile_loop(body: &[Node], span: Span, program: &mut Program) -> usize {
+    let begin = program.push(Bytecode::JumpIfZero(0), vec![span.start]);
+
+    compile_into(body, program);
+
+    let end = program.push(Bytecode::JumpUnlessZero(begin + 1), vec![span.end - 1]);
+    program.code[begin] = Bytecode::JumpIfZero(end + 1);
+
+    end + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::optimizer::{optimize, Optimizations};
+    use crate::parser::parse;
+
+    fn compiled(source: &str, optimizations: &Optimizations) -> Program {
+        compile(&optimize(&parse(lex(source)), optimizations))
+    }
+
+    #[test]
+    fn jump_targets() {
+        let program = compiled("+[>[-]<]", &Optimizations::none());
+
+        assert_eq!(program.code, vec![
+            Bytecode::Add(1),
+            Bytecode::JumpIfZero(8),
+            Bytecode::Move(1),
+            Bytecode::JumpIfZero(6),
+            Bytecode::Add(-1),
+            Bytecode::JumpUnlessZero(4),
+            Bytecode::Move(-1),
+            Bytecode::JumpUnlessZero(2),
+        ]);
+        assert_eq!(program.offsets[7], vec![7]);
+    }
+
+    #[test]
+    fn idioms_are_followed_by_their_loop() {
+        let program = compiled("[-]+", &Optimizations::default());
+
+        assert_eq!(program.code, vec![
+            Bytecode::Idiom(Idiom::SetZero, 4),
+            Bytecode::JumpIfZero(4),
+            Bytecode::Add(-1),
+            Bytecode::JumpUnlessZero(2),
+            Bytecode::Add(1),
+        ]);
+        assert_eq!(program.offsets[0], vec![0, 1, 2]);
+        assert_eq!(program.offsets[3], vec![2]);
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 1000;
+        let source = "[".repeat(depth) + &"]".repeat(depth);
+        let program = compiled(&source, &Optimizations::none());
+
+        assert_eq!(program.len(), 2 * depth);
+        assert_eq!(program.code[0], Bytecode::JumpIfZero(2 * depth));
+        assert_eq!(program.code[2 * depth - 1], Bytecode::JumpUnlessZero(1));
+    }
+}
@@ -0,0 +1,633 @@
+use super::describe;
+use crate::config::{Config, Eof, Overflow, TapeGrowth};
+use crate::optimizer::{Idiom, Node, Op};
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Translates the optimized program into x86-64 assembly for the GNU
+/// assembler, in Intel syntax, for a standalone Linux executable that talks
+/// to the kernel with raw `read`, `write`, `mmap` and `exit` system calls.
+/// It behaves like `run` with `config`, and like the C translation prints
+/// the message `run` stops with to stderr and exits with 1. Folded additions
+/// and moves and `[-]` are single instructions on a wrapping tape.
+pub fn to_asm(nodes: &[Node], config: &Config) -> String {
+    let mut generator = Generator{config, width: config.cell_type.bits() / 8, labels: 0, body: String::new(), stubs: String::new(), data: String::new()};
+    generator.block(nodes);
+
+    let mut out = format!("# Brainfuck program translated by bf compile {}\n\n", describe(config));
+    out.push_str("    .intel_syntax noprefix\n    .globl _start\n\n    .text\n");
+    out.push_str("# rbx points at the current cell, r12 at the start of the memory for the\n");
+    out.push_str("# tape and r13 just past its end, r15 counts the bytes in the output buffer\n");
+    out.push_str("_start:\n");
+    generator.start(&mut out);
+    out.push_str(&generator.body);
+    line(&mut out, "call flush");
+    line(&mut out, "xor edi, edi");
+    line(&mut out, "jmp exit");
+    out.push_str(&generator.stubs);
+    generator.routines(&mut out);
+
+    out.push_str("\n    .section .rodata\n");
+    out.push_str(&generator.data);
+    out.push_str(&generator.messages());
+
+    out.push_str("\n    .bss\n");
+    if config.growth == TapeGrowth::Fixed {
+        line(&mut out, &format!(".lcomm tape, {}", tape_bytes(config)));
+    }
+    line(&mut out, &format!(".lcomm output, {}", OUTPUT_BUFFER));
+    line(&mut out, ".lcomm input, 1");
+    line(&mut out, ".lcomm number, 24");
+    out.push_str("\n    .section .note.GNU-stack, \"\", @progbits\n");
+    out
+}
+
+/// Assembles `assembly` with `as` and links it with `ld` into the executable
+/// `output`.
+pub fn assemble(assembly: &str, output: &Path) -> Result<(), String> {
+    let mut object = output.as_os_str().to_owned();
+    object.push(".o");
+
+    let mut child = Command::new("as").arg("--64").arg("-o").arg(&object).stdin(Stdio::piped()).spawn().map_err(|e| format!("cannot run as: {}", e))?;
+    //as reads the source from stdin when it is given no files
+    child.stdin.take().unwrap().write_all(assembly.as_bytes()).map_err(|e| format!("cannot write to as: {}", e))?;
+    let assembled = child.wait().map_err(|e| format!("cannot run as: {}", e))?;
+    if !assembled.success() {
+        return Err(String::from("as failed"));
+    }
+
+    let linked = Command::new("ld").arg("-o").arg(output).arg(&object).status();
+    let _ = fs::remove_file(&object);
+    match linked {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => Err(String::from("ld failed")),
+        Err(e) => Err(format!("cannot run ld: {}", e))
+    }
+}
+
+const OUTPUT_BUFFER: usize = 4096;
+
+//the address space a growing tape may take up; pages are only used once
+//the program gets to them
+const RESERVED: u64 = 1 << 32;
+
+fn tape_bytes(config: &Config) -> usize {
+    config.tape_length.max(1) * (config.cell_type.bits() / 8) as usize
+}
+
+fn line(out: &mut String, text: &str) {
+    out.push_str("    ");
+    out.push_str(text);
+    out.push('\n');
+}
+
+fn label(out: &mut String, name: &str) {
+    out.push_str(name);
+    out.push_str(":\n");
+}
+
+//a string for .ascii
+fn quoted(text: &str) -> String {
+    let escaped: String = text.bytes().map(|byte| match byte {
+        b'"' | b'\\' => format!("\\{}", byte as char),
+        b' '..=b'~' => (byte as char).to_string(),
+        _ => format!("\\{:03o}", byte),
+    }).collect();
+    format!("\"{}\"", escaped)
+}
+
+//the error messages, with their lengths
+const MESSAGES: [(&str, &str); 6] = [
+    ("error", "Error: "),
+    ("at_index", " at index "),
+    ("overflow", "Addition overflow"),
+    ("underflow", "Subtraction underflow"),
+    ("out_of_bounds", "Data pointer out of bounds"),
+    ("end_of_input", "End of input"),
+];
+
+fn message_length(name: &str) -> usize {
+    MESSAGES.iter().find(|(label, _)| *label == name).unwrap().1.len()
+}
+
+struct Generator<'a> {
+    config: &'a Config,
+    width: u32,
+    labels: usize,
+    body: String,
+    //code that is jumped to when a move leaves the tape
+    stubs: String,
+    //the tables of characters of the instructions that can fail
+    data: String,
+}
+
+impl<'a> Generator<'a> {
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels
+    }
+
+    fn size(&self) -> &'static str {
+        match self.width {
+            1 => "byte",
+            2 => "word",
+            _ => "dword",
+        }
+    }
+
+    //the part of a register as wide as a cell, for a register like rcx
+    fn register(&self, register: char) -> String {
+        match self.width {
+            1 => format!("{}l", register),
+            2 => format!("{}x", register),
+            _ => format!("e{}x", register),
+        }
+    }
+
+    //the value as a signed number as wide as a cell, which is the same
+    //modulo the cell size
+    fn immediate(&self, value: i64) -> i64 {
+        match self.width {
+            1 => value as i8 as i64,
+            2 => value as i16 as i64,
+            _ => value as i32 as i64,
+        }
+    }
+
+    //the label of a table of the characters of the node
+    fn table(&mut self, node: &Node) -> String {
+        let name = format!(".Ltable{}", self.label());
+        let offsets: Vec<String> = node.offsets.iter().map(|offset| offset.to_string()).collect();
+        self.data.push_str(&format!("{}:\n    .long {}\n", name, offsets.join(", ")));
+        name
+    }
+
+    fn start(&self, out: &mut String) {
+        if self.config.growth == TapeGrowth::Fixed {
+            line(out, "lea r12, [rip + tape]");
+            line(out, &format!("mov r13, {}", tape_bytes(self.config)));
+            line(out, "add r13, r12");
+            line(out, "mov rbx, r12");
+        }
+        else {
+            //the tape grows into memory mapped up front
+            line(out, "mov eax, 9");
+            line(out, "xor edi, edi");
+            line(out, &format!("mov rsi, {}", RESERVED));
+            line(out, "mov edx, 3");
+            //MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE
+            line(out, "mov r10d, 0x4022");
+            line(out, "mov r8, -1");
+            line(out, "xor r9d, r9d");
+            line(out, "syscall");
+            line(out, "cmp rax, -4096");
+            line(out, "ja out_of_memory");
+            line(out, "mov r12, rax");
+            line(out, &format!("mov r13, {}", RESERVED));
+            line(out, "add r13, r12");
+            if self.config.growth == TapeGrowth::Both {
+                line(out, &format!("mov rbx, {}", RESERVED / 2));
+                line(out, "add rbx, r12");
+            }
+            else {
+                line(out, "mov rbx, r12");
+            }
+        }
+        line(out, "xor r15d, r15d");
+    }
+
+    fn block(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match &node.op {
+                Op::Add(amount) if self.config.overflow == Overflow::Wrap => {
+                    let value = self.immediate(*amount);
+                    let size = self.size();
+                    if value < 0 && self.immediate(-value) == -value {
+                        line(&mut self.body, &format!("sub {} ptr [rbx], {}", size, -value));
+                    }
+                    else {
+                        line(&mut self.body, &format!("add {} ptr [rbx], {}", size, value));
+                    }
+                },
+                Op::Add(amount) => {
+                    let table = if self.config.overflow == Overflow::Error { self.table(node) } else { String::from(".Lno_table") };
+                    line(&mut self.body, &format!("mov rdi, {}", amount));
+                    line(&mut self.body, &format!("lea rsi, [rip + {}]", table));
+                    line(&mut self.body, "call add_cell");
+                },
+                Op::Move(amount) => self.move_pointer(*amount, node),
+                Op::Write => line(&mut self.body, "call write_cell"),
+                Op::Read => {
+                    line(&mut self.body, &format!("mov edi, {}", node.offsets[0]));
+                    line(&mut self.body, "call read_cell");
+                },
+                Op::Loop(body) => self.loop_block(body),
+                //adding in one go only matches the loop when cells wrap
+                Op::Idiom(idiom, body) if self.config.overflow == Overflow::Wrap => self.idiom_block(idiom, body),
+                Op::Idiom(_, body) => self.loop_block(body),
+                Op::Breakpoint => (),
+                Op::Error(e) => {
+                    let name = format!(".Lerror{}", self.label());
+                    let text = format!("Error: {}\n", e);
+                    self.data.push_str(&format!("{}:\n    .ascii {}\n", name, quoted(&text)));
+                    line(&mut self.body, "call flush");
+                    line(&mut self.body, &format!("lea rsi, [rip + {}]", name));
+                    line(&mut self.body, &format!("mov edx, {}", text.len()));
+                    line(&mut self.body, "call report");
+                    line(&mut self.body, "mov edi, 1");
+                    line(&mut self.body, "jmp exit");
+                }
+            }
+        }
+    }
+
+    fn move_pointer(&mut self, amount: isize, node: &Node) {
+        line(&mut self.body, &format!("add rbx, {}", amount * self.width as isize));
+
+        //a tape that grows only runs out at the ends of the reserved memory
+        let growing = match self.config.growth {
+            TapeGrowth::Fixed => false,
+            TapeGrowth::Right => amount > 0,
+            TapeGrowth::Both => true,
+        };
+        let (compare, jump) = if amount > 0 { ("cmp rbx, r13", "jae") } else { ("cmp rbx, r12", "jb") };
+        line(&mut self.body, compare);
+        if growing {
+            line(&mut self.body, &format!("{} out_of_memory", jump));
+            return;
+        }
+
+        let stub = format!(".Lmove{}", self.label());
+        let table = self.table(node);
+        line(&mut self.body, &format!("{} {}", jump, stub));
+
+        label(&mut self.stubs, &stub);
+        line(&mut self.stubs, &format!("lea rsi, [rip + {}]", table));
+        line(&mut self.stubs, &format!("mov rdx, {}", amount));
+        line(&mut self.stubs, "jmp move_failed");
+    }
+
+    fn compare_zero(&mut self) {
+        let size = self.size();
+        line(&mut self.body, &format!("cmp {} ptr [rbx], 0", size));
+    }
+
+    fn loop_block(&mut self, body: &[Node]) {
+        let number = self.label();
+        self.compare_zero();
+        line(&mut self.body, &format!("je .Lend{}", number));
+        label(&mut self.body, &format!(".Lbody{}", number));
+        self.block(body);
+        self.compare_zero();
+        line(&mut self.body, &format!("jne .Lbody{}", number));
+        label(&mut self.body, &format!(".Lend{}", number));
+    }
+
+    fn idiom_block(&mut self, idiom: &Idiom, body: &[Node]) {
+        let size = self.size();
+        let targets = match idiom {
+            Idiom::SetZero => {
+                line(&mut self.body, &format!("mov {} ptr [rbx], 0", size));
+                return;
+            },
+            Idiom::MultiplyAdd(targets) => targets,
+            Idiom::Scan(_) => {
+                self.loop_block(body);
+                return;
+            }
+        };
+
+        //like run, the loop runs as written when a target is off the tape
+        let number = self.label();
+        for (distance, _) in targets {
+            line(&mut self.body, &format!("lea rax, [rbx + {}]", distance * self.width as isize));
+            line(&mut self.body, "cmp rax, r12");
+            line(&mut self.body, &format!("jb .Lloop{}", number));
+            line(&mut self.body, "cmp rax, r13");
+            line(&mut self.body, &format!("jae .Lloop{}", number));
+        }
+
+        let count = match self.width {
+            1 => "movzx eax, byte ptr [rbx]",
+            2 => "movzx eax, word ptr [rbx]",
+            _ => "mov eax, dword ptr [rbx]",
+        };
+        line(&mut self.body, count);
+        let product = self.register('c');
+        for (distance, factor) in targets {
+            //the loop ran count times modulo the cell size, so the product is too
+            line(&mut self.body, &format!("imul ecx, eax, {}", *factor as i32));
+            line(&mut self.body, &format!("add {} ptr [rbx + {}], {}", size, distance * self.width as isize, product));
+        }
+        line(&mut self.body, &format!("mov {} ptr [rbx], 0", size));
+        line(&mut self.body, &format!("jmp .Ldone{}", number));
+
+        label(&mut self.body, &format!(".Lloop{}", number));
+        self.loop_block(body);
+        label(&mut self.body, &format!(".Ldone{}", number));
+    }
+
+    //the subroutines, only those the program uses
+    fn routines(&self, out: &mut String) {
+        let code = format!("{}{}", self.body, self.stubs);
+        let store = self.register('a');
+        let size = self.size();
+
+        out.push_str("
+# writes the output buffer to stdout
+flush:
+    lea rsi, [rip + output]
+    mov rdx, r15
+1:
+    test rdx, rdx
+    jz 2f
+    mov eax, 1
+    mov edi, 1
+    syscall
+    test rax, rax
+    jle 2f
+    add rsi, rax
+    sub rdx, rax
+    jmp 1b
+2:
+    xor r15d, r15d
+    ret
+
+# writes the rdx bytes at rsi to stderr
+report:
+    mov eax, 1
+    mov edi, 2
+    syscall
+    ret
+
+# exits with the status in edi
+exit:
+    mov eax, 60
+    syscall
+
+out_of_memory:
+    call flush
+    lea rsi, [rip + no_memory]
+    mov edx, 21
+    call report
+    mov edi, 2
+    jmp exit
+
+# prints Error: followed by the rdx bytes at rsi and the index in rdi, and
+# exits with 1
+fail:
+    mov r12, rdi
+    mov r13, rsi
+    mov r14, rdx
+    call flush
+    lea rsi, [rip + error]
+    mov edx, 7
+    call report
+    mov rsi, r13
+    mov rdx, r14
+    call report
+    lea rsi, [rip + at_index]
+    mov edx, 10
+    call report
+    lea rcx, [rip + number + 23]
+    mov byte ptr [rcx], 10
+    mov rax, r12
+    mov r8d, 10
+1:
+    xor edx, edx
+    div r8
+    add dl, 48
+    dec rcx
+    mov byte ptr [rcx], dl
+    test rax, rax
+    jnz 1b
+    mov rsi, rcx
+    lea rdx, [rip + number + 24]
+    sub rdx, rcx
+    call report
+    mov edi, 1
+    jmp exit
+");
+
+        if code.contains("call write_cell") {
+            out.push_str(&format!("
+# puts the low byte of the cell in the output buffer
+write_cell:
+    mov al, byte ptr [rbx]
+    lea rdx, [rip + output]
+    mov byte ptr [rdx + r15], al
+    inc r15
+    cmp r15, {}
+    je flush
+    ret
+", OUTPUT_BUFFER));
+        }
+
+        if code.contains("jmp move_failed") {
+            out.push_str(&format!("
+# the move by rdx cells left the tape, rsi is the table of its characters
+move_failed:
+    mov rax, rbx
+    sub rax, r12
+    sar rax, {}
+    sub rax, rdx
+    test rdx, rdx
+    jle 1f
+    neg rax
+    mov rcx, {}
+    add rax, rcx
+1:
+    mov edi, dword ptr [rsi + rax * 4]
+    lea rsi, [rip + out_of_bounds]
+    mov edx, {}
+    jmp fail
+", self.width.trailing_zeros(), self.config.tape_length.max(1) - 1, message_length("out_of_bounds")));
+        }
+
+        if code.contains("call add_cell") {
+            let load = match (self.width, self.config.cell_type.is_signed()) {
+                (1, false) => "movzx eax, byte ptr [rbx]",
+                (1, true) => "movsx rax, byte ptr [rbx]",
+                (2, false) => "movzx eax, word ptr [rbx]",
+                (2, true) => "movsx rax, word ptr [rbx]",
+                (_, false) => "mov eax, dword ptr [rbx]",
+                (_, true) => "movsxd rax, dword ptr [rbx]",
+            };
+            let limit = self.register('d');
+            let past_limit = match self.config.overflow {
+                Overflow::Error => format!("    lea r8, [rip + overflow]
+    mov r9d, {}
+    test rdi, rdi
+    jg 2f
+    lea r8, [rip + underflow]
+    mov r9d, {}
+2:
+    sub rdx, rcx
+    mov rax, rdx
+    neg rax
+    cmovl rax, rdx
+    mov edi, dword ptr [rsi + rax * 4]
+    mov rsi, r8
+    mov rdx, r9
+    jmp fail
+", message_length("overflow"), message_length("underflow")),
+                _ => String::from("    ret\n"),
+            };
+            out.push_str(&format!("
+# adds rdi to the cell, rsi is the table of the characters of the addition
+add_cell:
+    {}
+    mov rcx, rax
+    add rax, rdi
+    mov rdx, {}
+    cmp rax, rdx
+    jl 1f
+    mov rdx, {}
+    cmp rax, rdx
+    jg 1f
+    mov {} ptr [rbx], {}
+    ret
+1:
+    mov {} ptr [rbx], {}
+{}", load, self.config.cell_type.min(), self.config.cell_type.max(), size, store, size, limit, past_limit));
+        }
+
+        if code.contains("call read_cell") {
+            let end_of_input = match self.config.eof {
+                Eof::Error => format!("    lea rsi, [rip + end_of_input]\n    mov edx, {}\n    jmp fail\n", message_length("end_of_input")),
+                //the cell keeps its value
+                Eof::Unchanged => String::from("    ret\n"),
+                Eof::Zero => format!("    mov {} ptr [rbx], 0\n    ret\n", size),
+                Eof::MinusOne => format!("    mov {} ptr [rbx], -1\n    ret\n", size),
+            };
+            out.push_str(&format!("
+# reads a byte into the cell, edi is the index of the ,
+read_cell:
+    push rdi
+    call flush
+    xor eax, eax
+    xor edi, edi
+    lea rsi, [rip + input]
+    mov edx, 1
+    syscall
+    pop rdi
+    cmp rax, 1
+    jne 1f
+    movzx eax, byte ptr [rip + input]
+    mov {} ptr [rbx], {}
+    ret
+1:
+{}", size, store, end_of_input));
+        }
+    }
+
+    fn messages(&self) -> String {
+        let mut out = String::new();
+        for (name, text) in MESSAGES.iter() {
+            out.push_str(&format!("{}:\n    .ascii {}\n", name, quoted(text)));
+        }
+        out.push_str(&format!("no_memory:\n    .ascii {}\n", quoted("Error: out of memory\n")));
+        //the table of additions that saturate, which is never read
+        out.push_str(".Lno_table:\n    .long 0\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CellType;
+    use crate::lexer::lex;
+    use crate::optimizer::{optimize, Optimizations};
+    use crate::parser::parse;
+
+    //assembles and links the translation and runs it, None if there is no
+    //assembler
+    fn assemble_and_run(source: &str, config: &Config, input: &[u8]) -> Option<(Vec<u8>, String, Option<i32>)> {
+        use std::env;
+        use std::process;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+        if Command::new("as").arg("--version").stdout(Stdio::null()).status().is_err() {
+            println!("No as, skipping.");
+            return None;
+        }
+
+        let code = to_asm(&optimize(&parse(lex(source)), &config.optimizations), config);
+        let binary = env::temp_dir().join(format!("bf-asm-{}-{}", process::id(), PROGRAMS.fetch_add(1, Ordering::Relaxed)));
+        assemble(&code, &binary).unwrap();
+
+        let mut child = Command::new(&binary).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        fs::remove_file(&binary).unwrap();
+
+        Some((output.stdout, String::from_utf8(output.stderr).unwrap(), output.status.code()))
+    }
+
+    #[test]
+    fn single_instructions() {
+        let nodes = optimize(&parse(lex("+++>>[-]<--")), &Optimizations::default());
+        let code = to_asm(&nodes, &Config{overflow: Overflow::Wrap, cell_type: CellType::U16, ..Config::default()});
+
+        assert!(code.contains("    add word ptr [rbx], 3\n"));
+        assert!(code.contains("    add rbx, 4\n"));
+        assert!(code.contains("    mov word ptr [rbx], 0\n"));
+        assert!(code.contains("    sub word ptr [rbx], 2\n"));
+        assert!(!code.contains("add_cell:"));
+    }
+
+    #[test]
+    fn hello_world() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        for optimizations in [Optimizations::default(), Optimizations::none()] {
+            let config = Config{optimizations, ..Config::default()};
+            if let Some((output, _, status)) = assemble_and_run(source, &config, b"") {
+                assert_eq!(output, b"Hello World!\n");
+                assert_eq!(status, Some(0));
+            }
+        }
+    }
+
+    #[test]
+    fn honours_the_policies() {
+        let cat = Config{eof: Eof::Zero, ..Config::default()};
+        if let Some((output, _, _)) = assemble_and_run(",[.,]", &cat, b"cat") {
+            assert_eq!(output, b"cat");
+        }
+
+        let minus_one = Config{eof: Eof::MinusOne, overflow: Overflow::Wrap, cell_type: CellType::U16, ..Config::default()};
+        if let Some((output, _, _)) = assemble_and_run(",+[-.,+]", &minus_one, b"ab") {
+            assert_eq!(output, b"ab");
+        }
+
+        let error = Config{overflow: Overflow::Error, ..Config::default()};
+        if let Some((_, stderr, status)) = assemble_and_run("+ -- +", &error, b"") {
+            assert_eq!(stderr, "Error: Subtraction underflow at index 3\n");
+            assert_eq!(status, Some(1));
+        }
+
+        let saturate = Config{overflow: Overflow::Saturate, cell_type: CellType::I8, ..Config::default()};
+        if let Some((output, _, _)) = assemble_and_run(&format!("-.{}.", "+".repeat(300)), &saturate, b"") {
+            assert_eq!(output, [0xFF, 0x7F]);
+        }
+
+        let short = Config{tape_length: 3, ..Config::default()};
+        if let Some((_, stderr, status)) = assemble_and_run(">> >", &short, b"") {
+            assert_eq!(stderr, "Error: Data pointer out of bounds at index 3\n");
+            assert_eq!(status, Some(1));
+        }
+
+        let growing = Config{tape_length: 1, growth: TapeGrowth::Both, ..Config::default()};
+        if let Some((output, _, status)) = assemble_and_run("+++[<<<+++>>>-]<<<[>>>>>+<<<<<-]>>>>>.", &growing, b"") {
+            assert_eq!(output, [9]);
+            assert_eq!(status, Some(0));
+        }
+    }
+}
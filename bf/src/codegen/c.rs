@@ -0,0 +1,375 @@
+use super::describe;
+use crate::config::{Config, Eof, Overflow, TapeGrowth};
+use crate::optimizer::{Idiom, Node, Op};
+
+/// Translates the optimized program into a standalone C99 program that
+/// behaves like `run` with `config`: the same tape length and growth, cell
+/// width, overflow and end of input policies, reading stdin and writing
+/// stdout. Where `run` stops with an error, the C program prints the same
+/// message to stderr and exits with 1.
+pub fn to_c(nodes: &[Node], config: &Config) -> String {
+    let mut body = String::new();
+    block(nodes, config, 1, &mut body);
+
+    let mut out = prelude(config, &body);
+    out.push_str("int main(void)\n{\n");
+    line(&mut out, 1, &format!("length = {};", config.tape_length.max(1)));
+    line(&mut out, 1, "tape = calloc(length, sizeof *tape);");
+    line(&mut out, 1, "if (!tape) {");
+    line(&mut out, 2, "out_of_memory();");
+    line(&mut out, 1, "}");
+    line(&mut out, 1, "pointer = 0;");
+    out.push('\n');
+    out.push_str(&body);
+    line(&mut out, 1, "return 0;");
+    out.push_str("}\n");
+    out
+}
+
+//the declarations and the helpers the body calls, an unused static
+//function would be a warning
+fn prelude(config: &Config, body: &str) -> String {
+    let cell_type = config.cell_type;
+    let moves = body.contains("move_pointer(");
+    let adds = body.contains("add_cell(");
+    let reads = body.contains("read_cell(");
+    let fails = (moves && config.growth != TapeGrowth::Both) || (adds && config.overflow == Overflow::Error) || (reads && config.eof == Eof::Error);
+
+    let mut out = format!("/* Brainfuck program translated by bf compile {} */\n\n", describe(config));
+
+    out.push_str("#include <stdint.h>\n#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n\n");
+    out.push_str(&format!("/* cells are stored unsigned, and read as {} */\n", if cell_type.is_signed() { "signed" } else { "unsigned" }));
+    out.push_str(&format!("typedef uint{}_t cell;\n", cell_type.bits()));
+    out.push_str(&format!("#define CELL_MIN ({}LL)\n#define CELL_MAX {}LL\n#define CELL_VALUES {}LL\n\n", cell_type.min(), cell_type.max(), 1i64 << cell_type.bits()));
+    out.push_str("static cell *tape;\nstatic size_t length;\nstatic size_t pointer;\n\n");
+
+    if fails {
+        out.push_str("\
+static void fail(const char *message, long offset)
+{
+    fflush(stdout);
+    fprintf(stderr, \"Error: %s at index %ld\\n\", message, offset);
+    exit(1);
+}
+
+");
+    }
+    out.push_str("\
+static void out_of_memory(void)
+{
+    fflush(stdout);
+    fputs(\"Error: out of memory\\n\", stderr);
+    exit(2);
+}
+
+");
+
+    if moves && config.growth != TapeGrowth::Fixed {
+        out.push_str("\
+/* doubles the tape, the new cells are added after the old ones */
+static void grow_right(void)
+{
+    tape = realloc(tape, 2 * length * sizeof *tape);
+    if (!tape) {
+        out_of_memory();
+    }
+    memset(tape + length, 0, length * sizeof *tape);
+    length *= 2;
+}
+
+");
+    }
+    if moves && config.growth == TapeGrowth::Both {
+        out.push_str("\
+/* doubles the tape, the new cells are added before the old ones */
+static void grow_left(void)
+{
+    grow_right();
+    memmove(tape + length / 2, tape, length / 2 * sizeof *tape);
+    memset(tape, 0, length / 2 * sizeof *tape);
+    pointer += length / 2;
+}
+
+");
+    }
+
+    //what happens at either end of the tape
+    let (right_end, left_end) = match config.growth {
+        TapeGrowth::Fixed => ("fail(\"Data pointer out of bounds\", offsets[step]);", "fail(\"Data pointer out of bounds\", offsets[step]);"),
+        TapeGrowth::Right => ("grow_right();", "fail(\"Data pointer out of bounds\", offsets[step]);"),
+        TapeGrowth::Both => ("grow_right();", "grow_left();"),
+    };
+    if moves {
+        out.push_str(&format!("\
+/* true if the pointer can move distance cells without leaving the tape */
+static int reaches(long distance)
+{{
+    return distance >= 0 ? length - pointer > (size_t)distance : pointer >= (size_t)-distance;
+}}
+
+/* moves the pointer one cell at a time once it gets to an end of the tape,
+   offsets are the characters of the move */
+static void move_pointer(long distance, const long *offsets)
+{{
+    long step;
+
+    (void)offsets;
+    if (reaches(distance)) {{
+        pointer += (size_t)distance;
+        return;
+    }}
+    for (step = 0; step < labs(distance); step++) {{
+        if (distance > 0) {{
+            if (pointer + 1 == length) {{
+                {}
+            }}
+            pointer++;
+        }}
+        else {{
+            if (pointer == 0) {{
+                {}
+            }}
+            pointer--;
+        }}
+    }}
+}}
+
+", right_end, left_end));
+    }
+
+    if adds {
+        let value = if cell_type.is_signed() {
+            "(long long)stored > CELL_MAX ? (long long)stored - CELL_VALUES : (long long)stored"
+        }
+        else {
+            "(long long)stored"
+        };
+        let limit = match config.overflow {
+            Overflow::Error => "fail(amount > 0 ? \"Addition overflow\" : \"Subtraction underflow\", offsets[llabs(limit - before)]);",
+            _ => "return;",
+        };
+        out.push_str(&format!("\
+static long long value(cell stored)
+{{
+    return {};
+}}
+
+/* adds to the current cell, offsets are the characters of the addition */
+static void add_cell(long long amount, const long *offsets)
+{{
+    long long before = value(tape[pointer]);
+    long long after = before + amount;
+
+    (void)offsets;
+    if (after < CELL_MIN || after > CELL_MAX) {{
+        long long limit = after < CELL_MIN ? CELL_MIN : CELL_MAX;
+        tape[pointer] = (cell)limit;
+        {}
+    }}
+    tape[pointer] = (cell)after;
+}}
+
+", value, limit));
+    }
+
+    let end_of_input = match config.eof {
+        Eof::Error => "fail(\"End of input\", offset);",
+        Eof::Unchanged => "/* the cell keeps its value */",
+        Eof::Zero => "tape[pointer] = 0;",
+        Eof::MinusOne => "tape[pointer] = (cell)-1;",
+    };
+    if reads {
+        out.push_str(&format!("\
+static void read_cell(long offset)
+{{
+    int byte;
+
+    (void)offset;
+    fflush(stdout);
+    byte = getchar();
+    if (byte != EOF) {{
+        tape[pointer] = (cell)byte;
+        return;
+    }}
+    {}
+}}
+
+", end_of_input));
+    }
+
+    out
+}
+
+fn line(out: &mut String, depth: usize, text: &str) {
+    for _ in 0..depth {
+        out.push_str("    ");
+    }
+    out.push_str(text);
+    out.push('\n');
+}
+
+//the characters of a node as a C array, for the error messages
+fn offsets(node: &Node) -> String {
+    let offsets: Vec<String> = node.offsets.iter().map(|offset| offset.to_string()).collect();
+    format!("(const long[]){{{}}}", offsets.join(", "))
+}
+
+fn block(nodes: &[Node], config: &Config, depth: usize, out: &mut String) {
+    for node in nodes {
+        match &node.op {
+            Op::Add(amount) if config.overflow == Overflow::Wrap => {
+                let operator = if *amount > 0 { "+=" } else { "-=" };
+                line(out, depth, &format!("tape[pointer] {} {};", operator, amount.unsigned_abs()));
+            },
+            Op::Add(amount) => line(out, depth, &format!("add_cell({}, {});", amount, offsets(node))),
+            Op::Move(amount) => line(out, depth, &format!("move_pointer({}, {});", amount, offsets(node))),
+            Op::Write => line(out, depth, "putchar((unsigned char)tape[pointer]);"),
+            Op::Read => line(out, depth, &format!("read_cell({});", node.offsets[0])),
+            Op::Loop(body) => {
+                line(out, depth, "while (tape[pointer]) {");
+                block(body, config, depth + 1, out);
+                line(out, depth, "}");
+            },
+            //adding in one go only matches the loop when cells wrap
+            Op::Idiom(idiom, body) if config.overflow == Overflow::Wrap => idiom_block(idiom, body, config, depth, out),
+            Op::Idiom(_, body) => {
+                line(out, depth, "while (tape[pointer]) {");
+                block(body, config, depth + 1, out);
+                line(out, depth, "}");
+            },
+            Op::Breakpoint => (),
+            Op::Error(e) => {
+                line(out, depth, "fflush(stdout);");
+                line(out, depth, &format!("fputs(\"Error: {}\\n\", stderr);", e.replace('\\', "\\\\").replace('"', "\\\"")));
+                line(out, depth, "exit(1);");
+            }
+        }
+    }
+}
+
+fn idiom_block(idiom: &Idiom, body: &[Node], config: &Config, depth: usize, out: &mut String) {
+    let targets = match idiom {
+        Idiom::SetZero => {
+            line(out, depth, "tape[pointer] = 0;");
+            return;
+        },
+        Idiom::MultiplyAdd(targets) => targets,
+        Idiom::Scan(_) => {
+            line(out, depth, "while (tape[pointer]) {");
+            block(body, config, depth + 1, out);
+            line(out, depth, "}");
+            return;
+        }
+    };
+
+    //like run, the loop runs as written when a target is off the tape;
+    //the pointer is back on the counter after every round, so asking again
+    //gives the same answer
+    let checks: Vec<String> = targets.iter().map(|(distance, _)| format!("reaches({})", distance)).collect();
+    let checked = config.growth != TapeGrowth::Both;
+    if checked {
+        line(out, depth, "while (tape[pointer]) {");
+        line(out, depth + 1, &format!("if ({}) {{", checks.join(" && ")));
+    }
+    else {
+        line(out, depth, "if (tape[pointer]) {");
+    }
+
+    let inner = if checked { depth + 2 } else { depth + 1 };
+    line(out, inner, "cell count = tape[pointer];");
+    for (distance, factor) in targets {
+        //the loop ran count times modulo the cell size, so the product is too
+        let operator = if *factor > 0 { "+=" } else { "-=" };
+        let product = match factor.unsigned_abs() {
+            1 => String::from("count"),
+            factor => format!("(cell)({}ULL * count)", factor),
+        };
+        line(out, inner, &format!("move_pointer({}, NULL);", distance));
+        line(out, inner, &format!("tape[pointer] {} {};", operator, product));
+        line(out, inner, &format!("move_pointer({}, NULL);", -distance));
+    }
+    line(out, inner, "tape[pointer] = 0;");
+
+    if checked {
+        line(out, depth + 2, "break;");
+        line(out, depth + 1, "}");
+        block(body, config, depth + 1, out);
+    }
+    line(out, depth, "}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::build_and_run;
+    use crate::config::CellType;
+    use crate::lexer::lex;
+    use crate::optimizer::{optimize, Optimizations};
+    use crate::parser::parse;
+
+    //compiles the translation with the C compiler and runs it, None if there
+    //is no compiler
+    fn compile_and_run(source: &str, config: &Config, input: &[u8]) -> Option<(Vec<u8>, String, Option<i32>)> {
+        let code = to_c(&optimize(&parse(lex(source)), &config.optimizations), config);
+        build_and_run("program.c", &code, &["cc", "-std=c99", "-pedantic", "-Wall", "-Wextra", "-Werror"], input)
+    }
+
+    #[test]
+    fn declares_the_cell_type() {
+        let config = Config{cell_type: CellType::I16, ..Config::default()};
+        let code = to_c(&[], &config);
+
+        assert!(code.contains("typedef uint16_t cell;"));
+        assert!(code.contains("#define CELL_MIN (-32768LL)"));
+        assert!(code.contains("length = 30000;"));
+    }
+
+    #[test]
+    fn hello_world() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        for optimizations in [Optimizations::default(), Optimizations::none()] {
+            let config = Config{optimizations, ..Config::default()};
+            if let Some((output, _, status)) = compile_and_run(source, &config, b"") {
+                assert_eq!(output, b"Hello World!\n");
+                assert_eq!(status, Some(0));
+            }
+        }
+    }
+
+    #[test]
+    fn honours_the_policies() {
+        let cat = Config{eof: Eof::Zero, ..Config::default()};
+        if let Some((output, _, _)) = compile_and_run(",[.,]", &cat, b"cat") {
+            assert_eq!(output, b"cat");
+        }
+
+        let minus_one = Config{eof: Eof::MinusOne, overflow: Overflow::Wrap, cell_type: CellType::U16, ..Config::default()};
+        if let Some((output, _, _)) = compile_and_run(",+[-.,+]", &minus_one, b"ab") {
+            assert_eq!(output, b"ab");
+        }
+
+        let error = Config{overflow: Overflow::Error, ..Config::default()};
+        if let Some((_, stderr, status)) = compile_and_run("+ -- +", &error, b"") {
+            assert_eq!(stderr, "Error: Subtraction underflow at index 3\n");
+            assert_eq!(status, Some(1));
+        }
+
+        let saturate = Config{overflow: Overflow::Saturate, ..Config::default()};
+        if let Some((output, _, _)) = compile_and_run(&format!("-.{}.", "+".repeat(300)), &saturate, b"") {
+            assert_eq!(output, [0, 255]);
+        }
+
+        let short = Config{tape_length: 3, ..Config::default()};
+        if let Some((_, stderr, status)) = compile_and_run(">> >", &short, b"") {
+            assert_eq!(stderr, "Error: Data pointer out of bounds at index 3\n");
+            assert_eq!(status, Some(1));
+        }
+
+        let growing = Config{tape_length: 1, growth: TapeGrowth::Both, ..Config::default()};
+        if let Some((output, _, status)) = compile_and_run("+++[<<<+++>>>-]<<<[>>>>>+<<<<<-]>>>>>.", &growing, b"") {
+            assert_eq!(output, [9]);
+            assert_eq!(status, Some(0));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+mod asm;
+mod c;
+mod rust;
+mod wasm;
+
+pub use self::asm::{assemble, to_asm};
+pub use self::c::to_c;
+pub use self::rust::to_rust;
+pub use self::wasm::{to_wasm, to_wat};
+
+use crate::config::{CellType, Config, Eof, Overflow, TapeGrowth};
+use crate::optimizer::Node;
+
+use std::str::FromStr;
+
+/// A language `bf compile` translates programs to.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// A standalone C99 program, see `to_c`.
+    C,
+    /// A Rust program that needs nothing but the standard library, see
+    /// `to_rust`.
+    Rust,
+    /// A WebAssembly module in the text format, see `to_wat`.
+    Wat,
+    /// The same module in the binary format, see `to_wasm`.
+    Wasm,
+    /// x86-64 assembly for a standalone Linux executable, see `to_asm`.
+    X86_64Asm,
+}
+
+impl Target {
+    /// Translates the optimized program for a tape set up like `config`.
+    pub fn generate(self, nodes: &[Node], config: &Config) -> Vec<u8> {
+        match self {
+            Target::C => to_c(nodes, config).into_bytes(),
+            Target::Rust => to_rust(nodes, config).into_bytes(),
+            Target::Wat => to_wat(nodes, config).into_bytes(),
+            Target::Wasm => to_wasm(nodes, config),
+            Target::X86_64Asm => to_asm(nodes, config).into_bytes(),
+        }
+    }
+
+    /// Extension of the file the translation is usually saved to.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Target::C => "c",
+            Target::Rust => "rs",
+            Target::Wat => "wat",
+            Target::Wasm => "wasm",
+            Target::X86_64Asm => "s",
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Target, String> {
+        match name {
+            "c" => Ok(Target::C),
+            "rust" => Ok(Target::Rust),
+            "wat" => Ok(Target::Wat),
+            "wasm" => Ok(Target::Wasm),
+            "x86_64-asm" => Ok(Target::X86_64Asm),
+            _ => Err(format!("unknown target '{}', expected c, rust, wat, wasm or x86_64-asm", name))
+        }
+    }
+}
+
+//the settings a translation was made for, in the words of the bf options
+fn describe(config: &Config) -> String {
+    let cell = match config.cell_type {
+        CellType::U8 => "u8",
+        CellType::I8 => "i8",
+        CellType::U16 => "u16",
+        CellType::I16 => "i16",
+        CellType::U32 => "u32",
+        CellType::I32 => "i32",
+    };
+    let growth = match config.growth {
+        TapeGrowth::Fixed => "fixed",
+        TapeGrowth::Right => "right",
+        TapeGrowth::Both => "both",
+    };
+    let overflow = match config.overflow {
+        Overflow::Error => "error",
+        Overflow::Wrap => "wrap",
+        Overflow::Saturate => "saturate",
+    };
+    let eof = match config.eof {
+        Eof::Error => "error",
+        Eof::Unchanged => "unchanged",
+        Eof::Zero => "zero",
+        Eof::MinusOne => "minus-one",
+    };
+
+    format!("--tape-length {} --growth {} --overflow {} --cell {} --eof {}", config.tape_length, growth, overflow, cell, eof)
+}
+
+//writes the translation to file_name in a directory of its own, builds it
+//with the compiler command followed by -o and the paths, and runs it with
+//input; None if the compiler is not installed
+#[cfg(test)]
+fn build_and_run(file_name: &str, code: &str, compiler: &[&str], input: &[u8]) -> Option<(Vec<u8>, String, Option<i32>)> {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process::{self, Command, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    //tests run in parallel, so each program gets a directory of its own
+    static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+    let directory = env::temp_dir().join(format!("bf-codegen-{}-{}", process::id(), PROGRAMS.fetch_add(1, Ordering::Relaxed)));
+    fs::create_dir_all(&directory).unwrap();
+    let (source, binary) = (directory.join(file_name), directory.join("program"));
+    fs::write(&source, code).unwrap();
+
+    let built = Command::new(compiler[0]).args(&compiler[1..]).arg("-o").arg(&binary).arg(&source).status();
+    match built {
+        Ok(status) => assert!(status.success(), "{}", code),
+        Err(_) => {
+            println!("No {}, skipping.", compiler[0]);
+            fs::remove_dir_all(&directory).unwrap();
+            return None;
+        }
+    }
+
+    let mut child = Command::new(&binary).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+
+    Some((output.stdout, String::from_utf8(output.stderr).unwrap(), output.status.code()))
+}
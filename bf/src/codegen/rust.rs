@@ -0,0 +1,333 @@
+use super::describe;
+use crate::config::{CellType, Config, Eof, Overflow, TapeGrowth};
+use crate::optimizer::{Idiom, Node, Op};
+
+/// Translates the optimized program into a self-contained Rust program, a
+/// `fn main` with the tape and one `while` loop for every loop of the
+/// source, that `rustc` builds without this crate. It behaves like `run`
+/// with `config` the same way the output of `to_c` does.
+pub fn to_rust(nodes: &[Node], config: &Config) -> String {
+    let mut out = prelude(config);
+
+    out.push_str("fn main() {\n");
+    line(&mut out, 1, &format!("let mut m = Machine::new({});", config.tape_length.max(1)));
+    out.push('\n');
+    block(nodes, config, 1, &mut out);
+    line(&mut out, 1, "m.flush();");
+    out.push_str("}\n");
+    out
+}
+
+//the tape and the helpers the body calls
+fn prelude(config: &Config) -> String {
+    let cell_type = config.cell_type;
+    let mut out = format!("// Brainfuck program translated by bf compile {}\n\n", describe(config));
+
+    out.push_str("#![allow(dead_code)]\n\nuse std::io::{self, Read, Write};\nuse std::process;\n\n");
+    out.push_str(&format!("// cells are stored unsigned, and read as {}\n", if cell_type.is_signed() { "signed" } else { "unsigned" }));
+    out.push_str(&format!("type Cell = u{};\n\n", cell_type.bits()));
+    out.push_str(&format!("const CELL_MIN: i64 = {};\nconst CELL_MAX: i64 = {};\nconst CELL_VALUES: i64 = {};\n\n", cell_type.min(), cell_type.max(), 1i64 << cell_type.bits()));
+
+    //what happens at either end of the tape
+    let (right_end, left_end) = match config.growth {
+        TapeGrowth::Fixed => ("self.fail(\"Data pointer out of bounds\", offsets[step]);", "self.fail(\"Data pointer out of bounds\", offsets[step]);"),
+        TapeGrowth::Right => ("self.cells.push(0);", "self.fail(\"Data pointer out of bounds\", offsets[step]);"),
+        TapeGrowth::Both => ("self.cells.push(0);", "self.grow_left();"),
+    };
+    //the offsets only matter for errors, and the step only for its offset
+    let (step, unused_offsets) = match config.growth {
+        TapeGrowth::Both => ("_", "\n        let _ = offsets;"),
+        _ => ("step", ""),
+    };
+    let limit = match config.overflow {
+        Overflow::Error => "self.fail(if amount > 0 { \"Addition overflow\" } else { \"Subtraction underflow\" }, offsets[(limit - before).unsigned_abs() as usize]);",
+        _ => "let _ = offsets;\n            return;",
+    };
+    let value = if cell_type.is_signed() { "if stored as i64 > CELL_MAX { stored as i64 - CELL_VALUES } else { stored as i64 }" } else { "stored as i64" };
+    let end_of_input = match config.eof {
+        Eof::Error => "self.fail(\"End of input\", offset)",
+        Eof::Unchanged => "()",
+        Eof::Zero => "self.set(0)",
+        Eof::MinusOne => "self.set(Cell::MAX)",
+    };
+
+    out.push_str(&format!("\
+struct Machine {{
+    cells: Vec<Cell>,
+    pointer: usize,
+    output: io::BufWriter<io::Stdout>,
+}}
+
+fn value(stored: Cell) -> i64 {{
+    {value}
+}}
+
+impl Machine {{
+    fn new(length: usize) -> Machine {{
+        Machine {{
+            cells: vec![0; length],
+            pointer: 0,
+            output: io::BufWriter::new(io::stdout()),
+        }}
+    }}
+
+    fn fail(&mut self, message: &str, offset: usize) -> ! {{
+        self.flush();
+        eprintln!(\"Error: {{}} at index {{}}\", message, offset);
+        process::exit(1);
+    }}
+
+    fn flush(&mut self) {{
+        self.output.flush().expect(\"cannot write the output\");
+    }}
+
+    fn current(&self) -> Cell {{
+        self.cells[self.pointer]
+    }}
+
+    fn set(&mut self, value: Cell) {{
+        self.cells[self.pointer] = value;
+    }}
+
+    fn add(&mut self, amount: Cell) {{
+        self.cells[self.pointer] = self.current().wrapping_add(amount);
+    }}
+
+    fn sub(&mut self, amount: Cell) {{
+        self.cells[self.pointer] = self.current().wrapping_sub(amount);
+    }}
+
+    // adds without wrapping, offsets are the characters of the addition
+    fn add_limited(&mut self, amount: i64, offsets: &[usize]) {{
+        let before = value(self.current());
+        let after = before + amount;
+
+        if after < CELL_MIN || after > CELL_MAX {{
+            let limit = after.clamp(CELL_MIN, CELL_MAX);
+            self.set(limit as Cell);
+            {limit}
+        }}
+        self.set(after as Cell);
+    }}
+
+    // true if the pointer can move distance cells without leaving the tape
+    fn reaches(&self, distance: isize) -> bool {{
+        let target = self.pointer as isize + distance;
+        target >= 0 && (target as usize) < self.cells.len()
+    }}
+
+    // moves the pointer one cell at a time once it gets to an end of the
+    // tape, offsets are the characters of the move
+    fn move_by(&mut self, distance: isize, offsets: &[usize]) {{{unused_offsets}
+        if self.reaches(distance) {{
+            self.pointer = (self.pointer as isize + distance) as usize;
+            return;
+        }}
+        for {step} in 0..distance.unsigned_abs() {{
+            if distance > 0 {{
+                if self.pointer + 1 == self.cells.len() {{
+                    {right_end}
+                }}
+                self.pointer += 1;
+            }}
+            else {{
+                if self.pointer == 0 {{
+                    {left_end}
+                }}
+                self.pointer -= 1;
+            }}
+        }}
+    }}
+
+    // doubles the tape, the new cells are added before the old ones
+    fn grow_left(&mut self) {{
+        let extra = self.cells.len();
+        self.cells.splice(0..0, std::iter::repeat(0).take(extra));
+        self.pointer += extra;
+    }}
+
+    // adds the current cell times each factor to the cell at its distance,
+    // and clears it
+    fn multiply_add(&mut self, targets: &[(isize, Cell)]) {{
+        let count = self.current();
+        for &(distance, factor) in targets {{
+            self.move_by(distance, &[]);
+            self.add(count.wrapping_mul(factor));
+            self.move_by(-distance, &[]);
+        }}
+        self.set(0);
+    }}
+
+    fn write(&mut self) {{
+        let byte = self.current() as u8;
+        self.output.write_all(&[byte]).expect(\"cannot write the output\");
+    }}
+
+    fn read(&mut self, offset: usize) {{
+        let _ = offset;
+        self.flush();
+
+        let mut byte = [0];
+        match io::stdin().read(&mut byte) {{
+            Ok(1) => self.set(byte[0] as Cell),
+            _ => {end_of_input}
+        }}
+    }}
+}}
+
+"));
+
+    out
+}
+
+fn line(out: &mut String, depth: usize, text: &str) {
+    for _ in 0..depth {
+        out.push_str("    ");
+    }
+    out.push_str(text);
+    out.push('\n');
+}
+
+fn offsets(node: &Node) -> String {
+    let offsets: Vec<String> = node.offsets.iter().map(|offset| offset.to_string()).collect();
+    format!("&[{}]", offsets.join(", "))
+}
+
+//an amount as the cell type, which wraps it like the cell would
+fn wrapped(amount: i64, cell_type: CellType) -> i64 {
+    amount.rem_euclid(1 << cell_type.bits())
+}
+
+fn block(nodes: &[Node], config: &Config, depth: usize, out: &mut String) {
+    for node in nodes {
+        match &node.op {
+            Op::Add(amount) if config.overflow == Overflow::Wrap => {
+                let method = if *amount > 0 { "add" } else { "sub" };
+                line(out, depth, &format!("m.{}({});", method, wrapped(amount.abs(), config.cell_type)));
+            },
+            Op::Add(amount) => line(out, depth, &format!("m.add_limited({}, {});", amount, offsets(node))),
+            Op::Move(amount) => line(out, depth, &format!("m.move_by({}, {});", amount, offsets(node))),
+            Op::Write => line(out, depth, "m.write();"),
+            Op::Read => line(out, depth, &format!("m.read({});", node.offsets[0])),
+            Op::Loop(body) => {
+                line(out, depth, "while m.current() != 0 {");
+                block(body, config, depth + 1, out);
+                line(out, depth, "}");
+            },
+            //adding in one go only matches the loop when cells wrap
+            Op::Idiom(idiom, body) if config.overflow == Overflow::Wrap => idiom_block(idiom, body, config, depth, out),
+            Op::Idiom(_, body) => {
+                line(out, depth, "while m.current() != 0 {");
+                block(body, config, depth + 1, out);
+                line(out, depth, "}");
+            },
+            Op::Breakpoint => (),
+            Op::Error(e) => {
+                line(out, depth, "m.flush();");
+                line(out, depth, &format!("eprintln!(\"Error: {{}}\", {:?});", e));
+                line(out, depth, "process::exit(1);");
+            }
+        }
+    }
+}
+
+fn idiom_block(idiom: &Idiom, body: &[Node], config: &Config, depth: usize, out: &mut String) {
+    let targets = match idiom {
+        Idiom::SetZero => {
+            line(out, depth, "m.set(0);");
+            return;
+        },
+        Idiom::MultiplyAdd(targets) => targets,
+        Idiom::Scan(_) => {
+            line(out, depth, "while m.current() != 0 {");
+            block(body, config, depth + 1, out);
+            line(out, depth, "}");
+            return;
+        }
+    };
+
+    let factors: Vec<String> = targets.iter().map(|(distance, factor)| format!("({}, {})", distance, wrapped(*factor, config.cell_type))).collect();
+    let multiply = format!("m.multiply_add(&[{}]);", factors.join(", "));
+
+    //like run, the loop runs as written when a target is off the tape; the
+    //pointer is back on the counter after every round, so asking again
+    //gives the same answer
+    if config.growth == TapeGrowth::Both {
+        line(out, depth, "if m.current() != 0 {");
+        line(out, depth + 1, &multiply);
+        line(out, depth, "}");
+        return;
+    }
+
+    let checks: Vec<String> = targets.iter().map(|(distance, _)| match config.growth {
+        TapeGrowth::Right if *distance > 0 => String::from("true"),
+        _ => format!("m.reaches({})", distance),
+    }).filter(|check| check != "true").collect();
+
+    line(out, depth, "while m.current() != 0 {");
+    if checks.is_empty() {
+        line(out, depth + 1, &multiply);
+        line(out, depth + 1, "break;");
+    }
+    else {
+        line(out, depth + 1, &format!("if {} {{", checks.join(" && ")));
+        line(out, depth + 2, &multiply);
+        line(out, depth + 2, "break;");
+        line(out, depth + 1, "}");
+        block(body, config, depth + 1, out);
+    }
+    line(out, depth, "}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::build_and_run;
+    use crate::lexer::lex;
+    use crate::optimizer::{optimize, Optimizations};
+    use crate::parser::parse;
+
+    //builds the translation with rustc and runs it, None if there is no rustc
+    fn compile_and_run(source: &str, config: &Config, input: &[u8]) -> Option<(Vec<u8>, String, Option<i32>)> {
+        let code = to_rust(&optimize(&parse(lex(source)), &config.optimizations), config);
+        build_and_run("program.rs", &code, &["rustc", "--edition", "2018", "-D", "warnings"], input)
+    }
+
+    #[test]
+    fn loops_are_while_loops() {
+        let code = to_rust(&optimize(&parse(lex("+++[>,.<-]")), &Optimizations::default()), &Config{overflow: Overflow::Wrap, ..Config::default()});
+
+        assert!(code.contains("type Cell = u8;"));
+        assert!(code.contains("    m.add(3);\n    while m.current() != 0 {\n        m.move_by(1, &[4]);\n        m.read(5);\n"));
+    }
+
+    #[test]
+    fn hello_world() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        if let Some((output, _, status)) = compile_and_run(source, &Config::default(), b"") {
+            assert_eq!(output, b"Hello World!\n");
+            assert_eq!(status, Some(0));
+        }
+    }
+
+    #[test]
+    fn honours_the_policies() {
+        let cat = Config{eof: Eof::Zero, cell_type: CellType::I16, ..Config::default()};
+        if let Some((output, _, _)) = compile_and_run(",[.,]", &cat, b"cat") {
+            assert_eq!(output, b"cat");
+        }
+
+        let error = Config{overflow: Overflow::Error, ..Config::default()};
+        if let Some((_, stderr, status)) = compile_and_run("+ -- +", &error, b"") {
+            assert_eq!(stderr, "Error: Subtraction underflow at index 3\n");
+            assert_eq!(status, Some(1));
+        }
+
+        let growing = Config{tape_length: 1, growth: TapeGrowth::Both, ..Config::default()};
+        if let Some((output, _, status)) = compile_and_run("+++[<<<+++>>>-]<<<[>>>>>+<<<<<-]>>>>>.", &growing, b"") {
+            assert_eq!(output, [9]);
+            assert_eq!(status, Some(0));
+        }
+    }
+}
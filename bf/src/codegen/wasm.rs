@@ -0,0 +1,1239 @@
+use super::describe;
+use crate::config::{CellType, Config, Eof, Overflow, TapeGrowth};
+use crate::optimizer::{Idiom, Node, Op};
+
+/// Translates the optimized program into a WebAssembly module in the text
+/// format that behaves like `run` with `config`.
+///
+/// The module imports `env.read`, which gives the next byte of input or -1
+/// at the end of it, and `env.write`, which takes a byte of output. It
+/// exports its `memory`, with the tape starting at the address in the global
+/// `tape`, and a `run` function that runs the program once. `run` returns 0
+/// when the program ends. Where `run` would stop with an error it returns 1
+/// for an addition overflow, 2 for a subtraction underflow, 3 when the data
+/// pointer leaves the tape, 4 at the end of input and 5 when the tape cannot
+/// grow, with the index of the character in the global `offset`.
+pub fn to_wat(nodes: &[Node], config: &Config) -> String {
+    Module::new(nodes, config).text(config)
+}
+
+/// Translates the optimized program into the binary form of the module
+/// `to_wat` gives.
+pub fn to_wasm(nodes: &[Node], config: &Config) -> Vec<u8> {
+    Module::new(nodes, config).binary()
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq)]
+enum ValType {
+    I32,
+    I64,
+}
+
+impl ValType {
+    fn name(self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7F,
+            ValType::I64 => 0x7E,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq)]
+enum Global {
+    //the current cell, counted in cells from the start of the tape
+    Pointer,
+    //cells on the tape
+    Length,
+    //the character the program stopped at with an error
+    Offset,
+    //address of the first cell
+    Tape,
+}
+
+const GLOBALS: [Global; 4] = [Global::Pointer, Global::Length, Global::Offset, Global::Tape];
+
+impl Global {
+    fn name(self) -> &'static str {
+        match self {
+            Global::Pointer => "pointer",
+            Global::Length => "length",
+            Global::Offset => "offset",
+            Global::Tape => "tape",
+        }
+    }
+}
+
+//the imports, run and the helpers it may call, in the order of their indices
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq)]
+enum Function {
+    Read,
+    Write,
+    Run,
+    Cell,
+    Reaches,
+    Move,
+    GrowRight,
+    GrowLeft,
+    Add,
+    ReadCell,
+}
+
+const FUNCTIONS: [Function; 10] = [Function::Read, Function::Write, Function::Run, Function::Cell, Function::Reaches, Function::Move, Function::GrowRight, Function::GrowLeft, Function::Add, Function::ReadCell];
+
+impl Function {
+    fn name(self) -> &'static str {
+        match self {
+            Function::Read => "read",
+            Function::Write => "write",
+            Function::Run => "run",
+            Function::Cell => "cell",
+            Function::Reaches => "reaches",
+            Function::Move => "move",
+            Function::GrowRight => "grow_right",
+            Function::GrowLeft => "grow_left",
+            Function::Add => "add",
+            Function::ReadCell => "read_cell",
+        }
+    }
+
+    fn params(self) -> &'static [ValType] {
+        match self {
+            Function::Write | Function::Reaches | Function::ReadCell => &[ValType::I32],
+            Function::Move => &[ValType::I32, ValType::I32],
+            Function::Add => &[ValType::I64, ValType::I32],
+            _ => &[]
+        }
+    }
+
+    //every function but write gives an address, a flag or a status
+    fn results(self) -> &'static [ValType] {
+        match self {
+            Function::Write => &[],
+            _ => &[ValType::I32]
+        }
+    }
+
+    fn is_import(self) -> bool {
+        self == Function::Read || self == Function::Write
+    }
+}
+
+//an instruction, with its name in the text format and its opcode
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq)]
+enum Ins {
+    //no immediates
+    Plain(&'static str, u8),
+    //a local or a label depth
+    Index(&'static str, u8, u32),
+    Global(&'static str, u8, Global),
+    Call(Function),
+    I32(i32),
+    I64(i64),
+    //a load or store with the log2 of its natural alignment
+    Memory(&'static str, u8, u32),
+    //block, loop or if without a result
+    Structured(&'static str, u8),
+    //memory.size and memory.grow, which are followed by a zero byte
+    Sized(&'static str, u8),
+    //memory.copy and memory.fill
+    Bulk(&'static str, u32),
+}
+
+const UNREACHABLE: Ins = Ins::Plain("unreachable", 0x00);
+const BLOCK: Ins = Ins::Structured("block", 0x02);
+const LOOP: Ins = Ins::Structured("loop", 0x03);
+const IF: Ins = Ins::Structured("if", 0x04);
+const ELSE: Ins = Ins::Plain("else", 0x05);
+const END: Ins = Ins::Plain("end", 0x0B);
+const RETURN: Ins = Ins::Plain("return", 0x0F);
+const SELECT: Ins = Ins::Plain("select", 0x1B);
+const I32_LOAD: Ins = Ins::Memory("i32.load", 0x28, 2);
+const MEMORY_SIZE: Ins = Ins::Sized("memory.size", 0x3F);
+const MEMORY_GROW: Ins = Ins::Sized("memory.grow", 0x40);
+const I32_EQZ: Ins = Ins::Plain("i32.eqz", 0x45);
+const I32_EQ: Ins = Ins::Plain("i32.eq", 0x46);
+const I32_LT_S: Ins = Ins::Plain("i32.lt_s", 0x48);
+const I32_GT_S: Ins = Ins::Plain("i32.gt_s", 0x4A);
+const I32_GT_U: Ins = Ins::Plain("i32.gt_u", 0x4B);
+const I32_GE_S: Ins = Ins::Plain("i32.ge_s", 0x4E);
+const I32_GE_U: Ins = Ins::Plain("i32.ge_u", 0x4F);
+const I64_LT_S: Ins = Ins::Plain("i64.lt_s", 0x53);
+const I64_GT_S: Ins = Ins::Plain("i64.gt_s", 0x55);
+const I64_GT_U: Ins = Ins::Plain("i64.gt_u", 0x56);
+const I32_ADD: Ins = Ins::Plain("i32.add", 0x6A);
+const I32_SUB: Ins = Ins::Plain("i32.sub", 0x6B);
+const I32_MUL: Ins = Ins::Plain("i32.mul", 0x6C);
+const I32_AND: Ins = Ins::Plain("i32.and", 0x71);
+const I32_OR: Ins = Ins::Plain("i32.or", 0x72);
+const I32_SHL: Ins = Ins::Plain("i32.shl", 0x74);
+const I32_SHR_U: Ins = Ins::Plain("i32.shr_u", 0x76);
+const I64_ADD: Ins = Ins::Plain("i64.add", 0x7C);
+const I64_SUB: Ins = Ins::Plain("i64.sub", 0x7D);
+const I64_MUL: Ins = Ins::Plain("i64.mul", 0x7E);
+const I64_SHR_U: Ins = Ins::Plain("i64.shr_u", 0x88);
+const I32_WRAP_I64: Ins = Ins::Plain("i32.wrap_i64", 0xA7);
+const I64_EXTEND_I32_U: Ins = Ins::Plain("i64.extend_i32_u", 0xAD);
+const MEMORY_COPY: Ins = Ins::Bulk("memory.copy", 10);
+const MEMORY_FILL: Ins = Ins::Bulk("memory.fill", 11);
+
+fn local_get(index: u32) -> Ins {
+    Ins::Index("local.get", 0x20, index)
+}
+
+fn local_set(index: u32) -> Ins {
+    Ins::Index("local.set", 0x21, index)
+}
+
+fn local_tee(index: u32) -> Ins {
+    Ins::Index("local.tee", 0x22, index)
+}
+
+fn br(depth: u32) -> Ins {
+    Ins::Index("br", 0x0C, depth)
+}
+
+fn br_if(depth: u32) -> Ins {
+    Ins::Index("br_if", 0x0D, depth)
+}
+
+fn global_get(global: Global) -> Ins {
+    Ins::Global("global.get", 0x23, global)
+}
+
+fn global_set(global: Global) -> Ins {
+    Ins::Global("global.set", 0x24, global)
+}
+
+//the cell as an unsigned i32
+fn load_cell(width: i32) -> Ins {
+    match width {
+        1 => Ins::Memory("i32.load8_u", 0x2D, 0),
+        2 => Ins::Memory("i32.load16_u", 0x2F, 1),
+        _ => I32_LOAD,
+    }
+}
+
+//the low bits of an i32 into the cell
+fn store_cell(width: i32) -> Ins {
+    match width {
+        1 => Ins::Memory("i32.store8", 0x3A, 0),
+        2 => Ins::Memory("i32.store16", 0x3B, 1),
+        _ => Ins::Memory("i32.store", 0x36, 2),
+    }
+}
+
+//the cell as an i64 holding its value as the cell type reads it
+fn load_value(cell_type: CellType) -> Ins {
+    match (cell_type.bits(), cell_type.is_signed()) {
+        (8, true) => Ins::Memory("i64.load8_s", 0x30, 0),
+        (8, false) => Ins::Memory("i64.load8_u", 0x31, 0),
+        (16, true) => Ins::Memory("i64.load16_s", 0x32, 1),
+        (16, false) => Ins::Memory("i64.load16_u", 0x33, 1),
+        (_, true) => Ins::Memory("i64.load32_s", 0x34, 2),
+        (_, false) => Ins::Memory("i64.load32_u", 0x35, 2),
+    }
+}
+
+//the low bits of an i64 into the cell
+fn store_value(width: i32) -> Ins {
+    match width {
+        1 => Ins::Memory("i64.store8", 0x3C, 0),
+        2 => Ins::Memory("i64.store16", 0x3D, 1),
+        _ => Ins::Memory("i64.store32", 0x3E, 2),
+    }
+}
+
+//the statuses of run
+const OVERFLOW: i32 = 1;
+const UNDERFLOW: i32 = 2;
+const OUT_OF_BOUNDS: i32 = 3;
+const END_OF_INPUT: i32 = 4;
+const OUT_OF_MEMORY: i32 = 5;
+
+const PAGE: i64 = 65536;
+
+#[derive(Debug)]
+struct Module {
+    //the characters of the moves and additions that can fail, as i32s at
+    //the start of the memory
+    tables: Vec<u8>,
+    tape: i32,
+    length: i32,
+    pages: u32,
+    //the functions the module defines, with their locals and code
+    functions: Vec<(Function, Vec<ValType>, Vec<Ins>)>,
+}
+
+impl Module {
+    fn new(nodes: &[Node], config: &Config) -> Module {
+        let width = (config.cell_type.bits() / 8) as i32;
+        let mut generator = Generator{config, width, tables: Vec::new(), code: Vec::new()};
+
+        generator.code.push(BLOCK);
+        generator.block(nodes, 0);
+        generator.code.push(END);
+        generator.code.push(local_get(0));
+
+        //the tape follows the tables, aligned for any cell
+        let tape = (generator.tables.len() as i32 + 15) / 16 * 16;
+        let length = config.tape_length.max(1) as i32;
+        let pages = ((tape as i64 + length as i64 * width as i64 + PAGE - 1) / PAGE).max(1) as u32;
+
+        let mut functions = vec![(Function::Run, vec![ValType::I32, ValType::I32], generator.code)];
+        //every helper a defined function calls, an unused one would only be
+        //noise in the text
+        let mut index = 0;
+        while index < functions.len() {
+            let calls: Vec<Function> = functions[index].2.iter().filter_map(|ins| match ins {
+                Ins::Call(function) => Some(*function),
+                _ => None
+            }).collect();
+            for function in calls {
+                if !function.is_import() && functions.iter().all(|(defined, _, _)| *defined != function) {
+                    let (locals, code) = helper(function, config, tape, width);
+                    functions.push((function, locals, code));
+                }
+            }
+            index += 1;
+        }
+        //in the order of FUNCTIONS, so indices only depend on what is there
+        functions.sort_by_key(|(function, _, _)| FUNCTIONS.iter().position(|f| f == function));
+
+        Module{tables: generator.tables, tape, length, pages, functions}
+    }
+
+    fn index(&self, function: Function) -> u32 {
+        if function.is_import() {
+            return FUNCTIONS.iter().position(|f| *f == function).unwrap() as u32;
+        }
+        2 + self.functions.iter().position(|(defined, _, _)| *defined == function).unwrap() as u32
+    }
+
+    fn initial(&self, global: Global) -> i32 {
+        match global {
+            Global::Length => self.length,
+            Global::Tape => self.tape,
+            Global::Pointer | Global::Offset => 0,
+        }
+    }
+
+    fn text(&self, config: &Config) -> String {
+        let mut out = format!(";; Brainfuck program translated by bf compile {}\n(module\n", describe(config));
+        out.push_str("  ;; the next byte of input, or -1 at the end of it\n");
+        out.push_str("  (import \"env\" \"read\" (func $read (result i32)))\n");
+        out.push_str("  (import \"env\" \"write\" (func $write (param i32)))\n");
+        out.push_str(&format!("  (memory (export \"memory\") {})\n", self.pages));
+        for global in GLOBALS.iter() {
+            let (export, mutable) = match global {
+                Global::Pointer | Global::Length => (String::new(), true),
+                Global::Offset => (String::from(" (export \"offset\")"), true),
+                Global::Tape => (String::from(" (export \"tape\")"), false),
+            };
+            let kind = if mutable { "(mut i32)" } else { "i32" };
+            out.push_str(&format!("  (global ${}{} {} (i32.const {}))\n", global.name(), export, kind, self.initial(*global)));
+        }
+        if !self.tables.is_empty() {
+            let bytes: String = self.tables.iter().map(|byte| format!("\\{:02x}", byte)).collect();
+            out.push_str(&format!("  (data (i32.const 0) \"{}\")\n", bytes));
+        }
+
+        for (function, locals, code) in &self.functions {
+            out.push_str(&format!("  (func ${}", function.name()));
+            if *function == Function::Run {
+                out.push_str(" (export \"run\")");
+            }
+            for (name, types) in [("param", function.params()), ("result", function.results()), ("local", locals.as_slice())] {
+                if !types.is_empty() {
+                    let types: Vec<&str> = types.iter().map(|t| t.name()).collect();
+                    out.push_str(&format!(" ({} {})", name, types.join(" ")));
+                }
+            }
+            out.push('\n');
+
+            let mut depth = 2;
+            for ins in code {
+                if let Ins::Plain("end", _) | Ins::Plain("else", _) = ins {
+                    depth -= 1;
+                }
+                for _ in 0..depth {
+                    out.push_str("  ");
+                }
+                out.push_str(&text(ins));
+                out.push('\n');
+                if let Ins::Structured(..) | Ins::Plain("else", _) = ins {
+                    depth += 1;
+                }
+            }
+            out.push_str("  )\n");
+        }
+        out.push_str(")\n");
+        out
+    }
+
+    fn binary(&self) -> Vec<u8> {
+        let mut out = b"\0asm".to_vec();
+        out.extend_from_slice(&[1, 0, 0, 0]);
+
+        //a type for every function, which may repeat
+        let functions: Vec<Function> = [Function::Read, Function::Write].iter().copied().chain(self.functions.iter().map(|(function, _, _)| *function)).collect();
+        let mut types = Vec::new();
+        vector(&mut types, functions.len());
+        for function in &functions {
+            types.push(0x60);
+            for list in [function.params(), function.results()] {
+                vector(&mut types, list.len());
+                types.extend(list.iter().map(|t| t.code()));
+            }
+        }
+        section(&mut out, 1, &types);
+
+        let mut imports = Vec::new();
+        vector(&mut imports, 2);
+        for (index, function) in [Function::Read, Function::Write].iter().enumerate() {
+            name(&mut imports, "env");
+            name(&mut imports, function.name());
+            imports.push(0x00);
+            unsigned(&mut imports, index as u64);
+        }
+        section(&mut out, 2, &imports);
+
+        let mut declarations = Vec::new();
+        vector(&mut declarations, self.functions.len());
+        for index in 0..self.functions.len() {
+            unsigned(&mut declarations, index as u64 + 2);
+        }
+        section(&mut out, 3, &declarations);
+
+        let mut memory = Vec::new();
+        vector(&mut memory, 1);
+        memory.push(0x00);
+        unsigned(&mut memory, self.pages as u64);
+        section(&mut out, 5, &memory);
+
+        let mut globals = Vec::new();
+        vector(&mut globals, GLOBALS.len());
+        for global in GLOBALS.iter() {
+            globals.push(ValType::I32.code());
+            globals.push(if *global == Global::Tape { 0 } else { 1 });
+            globals.push(0x41);
+            signed(&mut globals, self.initial(*global) as i64);
+            globals.push(0x0B);
+        }
+        section(&mut out, 6, &globals);
+
+        let mut exports = Vec::new();
+        vector(&mut exports, 4);
+        name(&mut exports, "memory");
+        exports.extend_from_slice(&[0x02, 0]);
+        name(&mut exports, "run");
+        exports.push(0x00);
+        unsigned(&mut exports, self.index(Function::Run) as u64);
+        for global in [Global::Offset, Global::Tape] {
+            name(&mut exports, global.name());
+            exports.push(0x03);
+            unsigned(&mut exports, global as u64);
+        }
+        section(&mut out, 7, &exports);
+
+        let mut bodies = Vec::new();
+        vector(&mut bodies, self.functions.len());
+        for (_, locals, code) in &self.functions {
+            let mut body = Vec::new();
+            vector(&mut body, locals.len());
+            for local in locals {
+                body.push(1);
+                body.push(local.code());
+            }
+            for ins in code {
+                self.encode(ins, &mut body);
+            }
+            body.push(0x0B);
+
+            vector(&mut bodies, body.len());
+            bodies.extend(body);
+        }
+        section(&mut out, 10, &bodies);
+
+        if !self.tables.is_empty() {
+            let mut data = Vec::new();
+            vector(&mut data, 1);
+            data.extend_from_slice(&[0x00, 0x41, 0x00, 0x0B]);
+            vector(&mut data, self.tables.len());
+            data.extend_from_slice(&self.tables);
+            section(&mut out, 11, &data);
+        }
+        out
+    }
+
+    fn encode(&self, ins: &Ins, out: &mut Vec<u8>) {
+        match *ins {
+            Ins::Plain(_, opcode) => out.push(opcode),
+            Ins::Index(_, opcode, index) => {
+                out.push(opcode);
+                unsigned(out, index as u64);
+            },
+            Ins::Global(_, opcode, global) => {
+                out.push(opcode);
+                unsigned(out, global as u64);
+            },
+            Ins::Call(function) => {
+                out.push(0x10);
+                unsigned(out, self.index(function) as u64);
+            },
+            Ins::I32(value) => {
+                out.push(0x41);
+                signed(out, value as i64);
+            },
+            Ins::I64(value) => {
+                out.push(0x42);
+                signed(out, value);
+            },
+            Ins::Memory(_, opcode, align) => {
+                out.push(opcode);
+                unsigned(out, align as u64);
+                unsigned(out, 0);
+            },
+            Ins::Structured(_, opcode) => out.extend_from_slice(&[opcode, 0x40]),
+            Ins::Sized(_, opcode) => out.extend_from_slice(&[opcode, 0x00]),
+            Ins::Bulk(_, code) => {
+                out.push(0xFC);
+                unsigned(out, code as u64);
+                //memory.copy has a source and a destination memory
+                let memories: &[u8] = if code == 10 { &[0, 0] } else { &[0] };
+                out.extend_from_slice(memories);
+            }
+        }
+    }
+}
+
+fn text(ins: &Ins) -> String {
+    match *ins {
+        Ins::Plain(name, _) | Ins::Memory(name, _, _) | Ins::Structured(name, _) | Ins::Sized(name, _) | Ins::Bulk(name, _) => String::from(name),
+        Ins::Index(name, _, index) => format!("{} {}", name, index),
+        Ins::Global(name, _, global) => format!("{} ${}", name, global.name()),
+        Ins::Call(function) => format!("call ${}", function.name()),
+        Ins::I32(value) => format!("i32.const {}", value),
+        Ins::I64(value) => format!("i64.const {}", value),
+    }
+}
+
+fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        //done once the rest is only the sign, which the byte carries
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn vector(out: &mut Vec<u8>, length: usize) {
+    unsigned(out, length as u64);
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    vector(out, name.len());
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn section(out: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    out.push(id);
+    vector(out, contents.len());
+    out.extend_from_slice(contents);
+}
+
+//the code of run, whose local 0 is the status and local 1 the count of a
+//multiplication
+struct Generator<'a> {
+    config: &'a Config,
+    width: i32,
+    tables: Vec<u8>,
+    code: Vec<Ins>,
+}
+
+impl<'a> Generator<'a> {
+    //the address of a table of the characters of the node
+    fn table(&mut self, node: &Node) -> i32 {
+        let address = self.tables.len() as i32;
+        for offset in &node.offsets {
+            self.tables.extend_from_slice(&(*offset as i32).to_le_bytes());
+        }
+        address
+    }
+
+    //stops run with the status the last call gave, depth is how many blocks
+    //are around the code in the one run stops with
+    fn check(&mut self, depth: u32) {
+        self.code.extend_from_slice(&[local_tee(0), br_if(depth)]);
+    }
+
+    fn move_pointer(&mut self, distance: i32, table: i32, depth: u32) {
+        self.code.extend_from_slice(&[Ins::I32(distance), Ins::I32(table), Ins::Call(Function::Move)]);
+        self.check(depth);
+    }
+
+    fn block(&mut self, nodes: &[Node], depth: u32) {
+        for node in nodes {
+            match &node.op {
+                Op::Add(amount) if self.config.overflow == Overflow::Wrap => {
+                    self.code.extend_from_slice(&[Ins::Call(Function::Cell), Ins::Call(Function::Cell), load_cell(self.width), Ins::I32(*amount as i32), I32_ADD, store_cell(self.width)]);
+                },
+                Op::Add(amount) => {
+                    let table = if self.config.overflow == Overflow::Error { self.table(node) } else { 0 };
+                    self.code.extend_from_slice(&[Ins::I64(*amount), Ins::I32(table), Ins::Call(Function::Add)]);
+                    self.check(depth);
+                },
+                Op::Move(amount) => {
+                    let table = if self.config.growth == TapeGrowth::Both { 0 } else { self.table(node) };
+                    self.move_pointer(*amount as i32, table, depth);
+                },
+                Op::Write => {
+                    //the low byte of the cell comes first
+                    self.code.extend_from_slice(&[Ins::Call(Function::Cell), load_cell(1), Ins::Call(Function::Write)]);
+                },
+                Op::Read => {
+                    self.code.extend_from_slice(&[Ins::I32(node.offsets[0] as i32), Ins::Call(Function::ReadCell)]);
+                    self.check(depth);
+                },
+                Op::Loop(body) => self.loop_block(body, depth),
+                //adding in one go only matches the loop when cells wrap
+                Op::Idiom(idiom, body) if self.config.overflow == Overflow::Wrap => self.idiom_block(idiom, body, depth),
+                Op::Idiom(_, body) => self.loop_block(body, depth),
+                Op::Breakpoint => (),
+                //unmatched brackets, which bf compile does not translate
+                Op::Error(_) => self.code.push(UNREACHABLE),
+            }
+        }
+    }
+
+    fn loop_block(&mut self, body: &[Node], depth: u32) {
+        self.code.extend_from_slice(&[BLOCK, LOOP, Ins::Call(Function::Cell), load_cell(self.width), I32_EQZ, br_if(1)]);
+        self.block(body, depth + 2);
+        self.code.extend_from_slice(&[br(0), END, END]);
+    }
+
+    //adds the count times the factor to the cell at distance
+    fn multiply(&mut self, distance: i32, factor: i64) {
+        let address = [Ins::Call(Function::Cell), Ins::I32(distance * self.width), I32_ADD];
+        self.code.extend_from_slice(&address);
+        self.code.extend_from_slice(&address);
+        self.code.extend_from_slice(&[load_cell(self.width), local_get(1), Ins::I32(factor as i32), I32_MUL, I32_ADD, store_cell(self.width)]);
+    }
+
+    fn idiom_block(&mut self, idiom: &Idiom, body: &[Node], depth: u32) {
+        let targets = match idiom {
+            Idiom::SetZero => {
+                self.code.extend_from_slice(&[Ins::Call(Function::Cell), Ins::I32(0), store_cell(self.width)]);
+                return;
+            },
+            Idiom::MultiplyAdd(targets) => targets,
+            Idiom::Scan(_) => {
+                self.loop_block(body, depth);
+                return;
+            }
+        };
+        let count = [Ins::Call(Function::Cell), load_cell(self.width), local_set(1)];
+        let zero = [Ins::Call(Function::Cell), Ins::I32(0), store_cell(self.width)];
+
+        //a tape that grows at both ends always reaches the targets, but
+        //moving to them may make it grow
+        if self.config.growth == TapeGrowth::Both {
+            self.code.extend_from_slice(&[Ins::Call(Function::Cell), load_cell(self.width), IF]);
+            self.code.extend_from_slice(&count);
+            for (distance, factor) in targets {
+                self.move_pointer(*distance as i32, 0, depth + 1);
+                self.multiply(0, *factor);
+                self.move_pointer(-*distance as i32, 0, depth + 1);
+            }
+            self.code.extend_from_slice(&zero);
+            self.code.push(END);
+            return;
+        }
+
+        //like run, the loop runs as written when a target is off the tape;
+        //the pointer is back on the counter after every round, so asking
+        //again gives the same answer
+        self.code.extend_from_slice(&[BLOCK, LOOP, Ins::Call(Function::Cell), load_cell(self.width), I32_EQZ, br_if(1)]);
+        for (index, (distance, _)) in targets.iter().enumerate() {
+            self.code.extend_from_slice(&[Ins::I32(*distance as i32), Ins::Call(Function::Reaches)]);
+            if index > 0 {
+                self.code.push(I32_AND);
+            }
+        }
+        self.code.push(IF);
+        self.code.extend_from_slice(&count);
+        for (distance, factor) in targets {
+            self.multiply(*distance as i32, *factor);
+        }
+        self.code.extend_from_slice(&zero);
+        self.code.extend_from_slice(&[br(2), END]);
+
+        self.block(body, depth + 2);
+        self.code.extend_from_slice(&[br(0), END, END]);
+    }
+}
+
+//the locals and code of a helper
+fn helper(function: Function, config: &Config, tape: i32, width: i32) -> (Vec<ValType>, Vec<Ins>) {
+    match function {
+        //the address of the current cell
+        Function::Cell => (vec![], vec![Ins::I32(tape), global_get(Global::Pointer), Ins::I32(width), I32_MUL, I32_ADD]),
+        //1 if the pointer can move the distance in local 0 without leaving
+        //the tape
+        Function::Reaches => (vec![], vec![
+            local_get(0), Ins::I32(0), I32_GE_S, IF,
+                global_get(Global::Length), global_get(Global::Pointer), I32_SUB, local_get(0), I32_GT_U, RETURN,
+            END,
+            global_get(Global::Pointer), Ins::I32(0), local_get(0), I32_SUB, I32_GE_U,
+        ]),
+        Function::Move => move_helper(config),
+        //doubles the tape, the new cells are added after the old ones
+        Function::GrowRight => (vec![ValType::I64, ValType::I32], vec![
+            //the pages the doubled tape needs, in local 2
+            Ins::I64(tape as i64), global_get(Global::Length), I64_EXTEND_I32_U, Ins::I64(2 * width as i64), I64_MUL, I64_ADD,
+            Ins::I64(PAGE - 1), I64_ADD, Ins::I64(16), I64_SHR_U, local_set(0),
+            local_get(0), Ins::I64(PAGE), I64_GT_U, IF, Ins::I32(OUT_OF_MEMORY), RETURN, END,
+            local_get(0), I32_WRAP_I64, MEMORY_SIZE, I32_SUB, local_tee(1), Ins::I32(0), I32_GT_S, IF,
+                local_get(1), MEMORY_GROW, Ins::I32(-1), I32_EQ, IF, Ins::I32(OUT_OF_MEMORY), RETURN, END,
+            END,
+            Ins::I32(tape), global_get(Global::Length), Ins::I32(width), I32_MUL, I32_ADD, Ins::I32(0), global_get(Global::Length), Ins::I32(width), I32_MUL, MEMORY_FILL,
+            global_get(Global::Length), Ins::I32(1), I32_SHL, global_set(Global::Length),
+            Ins::I32(0),
+        ]),
+        //doubles the tape, the new cells are added before the old ones
+        Function::GrowLeft => (vec![ValType::I32, ValType::I32], vec![
+            Ins::Call(Function::GrowRight), local_tee(0), IF, local_get(0), RETURN, END,
+            //the old cells move up by the bytes in local 1
+            Ins::I32(tape), global_get(Global::Length), Ins::I32(1), I32_SHR_U, Ins::I32(width), I32_MUL, local_tee(1), I32_ADD, Ins::I32(tape), local_get(1), MEMORY_COPY,
+            Ins::I32(tape), Ins::I32(0), local_get(1), MEMORY_FILL,
+            global_get(Global::Pointer), global_get(Global::Length), Ins::I32(1), I32_SHR_U, I32_ADD, global_set(Global::Pointer),
+            Ins::I32(0),
+        ]),
+        Function::Add => add_helper(config),
+        Function::ReadCell => {
+            let store = store_cell(width);
+            let end_of_input = match config.eof {
+                Eof::Error => vec![local_get(0), global_set(Global::Offset), Ins::I32(END_OF_INPUT)],
+                //the cell keeps its value
+                Eof::Unchanged => vec![Ins::I32(0)],
+                Eof::Zero => vec![Ins::Call(Function::Cell), Ins::I32(0), store, Ins::I32(0)],
+                Eof::MinusOne => vec![Ins::Call(Function::Cell), Ins::I32(-1), store, Ins::I32(0)],
+            };
+            let mut code = vec![
+                Ins::Call(Function::Read), local_tee(1), Ins::I32(0), I32_GE_S, IF,
+                    Ins::Call(Function::Cell), local_get(1), store, Ins::I32(0), RETURN,
+                END,
+            ];
+            code.extend(end_of_input);
+            (vec![ValType::I32], code)
+        },
+        Function::Read | Function::Write | Function::Run => unreachable!(),
+    }
+}
+
+//moves the pointer by the distance in local 0 one cell at a time once it
+//gets to an end of the tape, local 1 is the address of the table of the
+//characters of the move
+fn move_helper(config: &Config) -> (Vec<ValType>, Vec<Ins>) {
+    //step in local 2, steps to take in local 3, status of growing in local 4
+    let fail = vec![local_get(1), local_get(2), Ins::I32(2), I32_SHL, I32_ADD, I32_LOAD, global_set(Global::Offset), Ins::I32(OUT_OF_BOUNDS), RETURN];
+    let grow = |function| vec![Ins::Call(function), local_tee(4), IF, local_get(4), RETURN, END];
+    let (right_end, left_end) = match config.growth {
+        TapeGrowth::Fixed => (fail.clone(), fail),
+        TapeGrowth::Right => (grow(Function::GrowRight), fail),
+        TapeGrowth::Both => (grow(Function::GrowRight), grow(Function::GrowLeft)),
+    };
+
+    let mut code = vec![
+        local_get(0), Ins::Call(Function::Reaches), IF,
+            global_get(Global::Pointer), local_get(0), I32_ADD, global_set(Global::Pointer), Ins::I32(0), RETURN,
+        END,
+        Ins::I32(0), local_get(0), I32_SUB, local_get(0), local_get(0), Ins::I32(0), I32_LT_S, SELECT, local_set(3),
+        BLOCK, LOOP,
+            local_get(2), local_get(3), I32_GE_U, br_if(1),
+            local_get(0), Ins::I32(0), I32_GT_S, IF,
+                global_get(Global::Pointer), Ins::I32(1), I32_ADD, global_get(Global::Length), I32_EQ, IF,
+    ];
+    code.extend(right_end);
+    code.extend_from_slice(&[
+                END,
+                global_get(Global::Pointer), Ins::I32(1), I32_ADD, global_set(Global::Pointer),
+            ELSE,
+                global_get(Global::Pointer), I32_EQZ, IF,
+    ]);
+    code.extend(left_end);
+    code.extend_from_slice(&[
+                END,
+                global_get(Global::Pointer), Ins::I32(1), I32_SUB, global_set(Global::Pointer),
+            END,
+            local_get(2), Ins::I32(1), I32_ADD, local_set(2),
+            br(0),
+        END, END,
+        Ins::I32(0),
+    ]);
+    (vec![ValType::I32, ValType::I32, ValType::I32], code)
+}
+
+//adds the amount in local 0 to the current cell, local 1 is the address of
+//the table of the characters of the addition
+fn add_helper(config: &Config) -> (Vec<ValType>, Vec<Ins>) {
+    let cell_type = config.cell_type;
+    let width = (cell_type.bits() / 8) as i32;
+    let (min, max) = (Ins::I64(cell_type.min()), Ins::I64(cell_type.max()));
+
+    //the value before in local 2, after in local 3 and the limit it went
+    //past in local 4
+    let limit = match config.overflow {
+        Overflow::Error => vec![
+            //the table has the character at the distance from the value before to the limit
+            local_get(1),
+            local_get(4), local_get(2), I64_SUB, local_get(2), local_get(4), I64_SUB, local_get(0), Ins::I64(0), I64_GT_S, SELECT,
+            I32_WRAP_I64, Ins::I32(2), I32_SHL, I32_ADD, I32_LOAD, global_set(Global::Offset),
+            Ins::I32(OVERFLOW), Ins::I32(UNDERFLOW), local_get(0), Ins::I64(0), I64_GT_S, SELECT, RETURN,
+        ],
+        _ => vec![Ins::I32(0), RETURN],
+    };
+
+    let mut code = vec![
+        Ins::Call(Function::Cell), load_value(cell_type), local_tee(2), local_get(0), I64_ADD, local_tee(3), min, I64_LT_S,
+        local_get(3), max, I64_GT_S, I32_OR, IF,
+            min, max, local_get(3), min, I64_LT_S, SELECT, local_set(4),
+            Ins::Call(Function::Cell), local_get(4), store_value(width),
+    ];
+    code.extend(limit);
+    code.extend_from_slice(&[
+        END,
+        Ins::Call(Function::Cell), local_get(3), store_value(width),
+        Ins::I32(0),
+    ]);
+    (vec![ValType::I64, ValType::I64, ValType::I64], code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::optimizer::{optimize, Optimizations};
+    use crate::parser::parse;
+
+    //runs the module like bf run would, printing errors the same way
+    const HOST: &str = "
+const fs = require('fs');
+const input = fs.readFileSync(0);
+const output = [];
+let position = 0;
+const messages = ['', 'Addition overflow', 'Subtraction underflow', 'Data pointer out of bounds', 'End of input', 'Out of memory'];
+const env = {
+    read: () => position < input.length ? input[position++] : -1,
+    write: byte => output.push(byte),
+};
+WebAssembly.instantiate(fs.readFileSync(process.argv[1]), {env}).then(({instance}) => {
+    const status = instance.exports.run();
+    process.stdout.write(Buffer.from(output));
+    if (status !== 0) {
+        process.stderr.write(`Error: ${messages[status]} at index ${instance.exports.offset.value}\\n`);
+        process.exitCode = 1;
+    }
+});
+";
+
+    //runs the module with node, None if there is no node
+    fn run_in_node(source: &str, config: &Config, input: &[u8]) -> Option<(Vec<u8>, String, Option<i32>)> {
+        use std::env;
+        use std::fs;
+        use std::io::Write;
+        use std::process::{self, Command, Stdio};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static MODULES: AtomicUsize = AtomicUsize::new(0);
+
+        let module = to_wasm(&optimize(&parse(lex(source)), &config.optimizations), config);
+        let path = env::temp_dir().join(format!("bf-wasm-{}-{}.wasm", process::id(), MODULES.fetch_add(1, Ordering::Relaxed)));
+        fs::write(&path, module).unwrap();
+
+        let child = Command::new("node").arg("-e").arg(HOST).arg(&path).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(_) => {
+                println!("No node, skipping.");
+                fs::remove_file(&path).unwrap();
+                return None;
+            }
+        };
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        Some((output.stdout, String::from_utf8(output.stderr).unwrap(), output.status.code()))
+    }
+
+    //programs that between them need every helper, with the options for it
+    fn programs() -> Vec<(&'static str, Config)> {
+        vec![
+            ("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.", Config{overflow: Overflow::Wrap, ..Config::default()}),
+            (",[.,]>+[-<+>]<[>>+<<-]", Config{eof: Eof::Error, cell_type: CellType::I16, ..Config::default()}),
+            ("+[<<+>>-]>>[-]<[>+<-]", Config{overflow: Overflow::Error, growth: TapeGrowth::Both, tape_length: 1, ..Config::default()}),
+            ("-[>+>++<<-],+[-.,+]", Config{overflow: Overflow::Saturate, growth: TapeGrowth::Right, cell_type: CellType::U32, eof: Eof::MinusOne, ..Config::default()}),
+        ]
+    }
+
+    //the text format name of every opcode the modules use, as the spec has
+    //them, so the binary can be read back without the encoder
+    const OPCODES: [(u8, &str); 58] = [
+        (0x00, "unreachable"), (0x02, "block"), (0x03, "loop"), (0x04, "if"), (0x05, "else"), (0x0B, "end"),
+        (0x0C, "br"), (0x0D, "br_if"), (0x0F, "return"), (0x10, "call"), (0x1B, "select"),
+        (0x20, "local.get"), (0x21, "local.set"), (0x22, "local.tee"), (0x23, "global.get"), (0x24, "global.set"),
+        (0x28, "i32.load"), (0x2D, "i32.load8_u"), (0x2F, "i32.load16_u"), (0x30, "i64.load8_s"), (0x31, "i64.load8_u"),
+        (0x32, "i64.load16_s"), (0x33, "i64.load16_u"), (0x34, "i64.load32_s"), (0x35, "i64.load32_u"),
+        (0x36, "i32.store"), (0x3A, "i32.store8"), (0x3B, "i32.store16"), (0x3C, "i64.store8"), (0x3D, "i64.store16"), (0x3E, "i64.store32"),
+        (0x3F, "memory.size"), (0x40, "memory.grow"), (0x41, "i32.const"), (0x42, "i64.const"),
+        (0x45, "i32.eqz"), (0x46, "i32.eq"), (0x48, "i32.lt_s"), (0x4A, "i32.gt_s"), (0x4B, "i32.gt_u"), (0x4E, "i32.ge_s"), (0x4F, "i32.ge_u"),
+        (0x53, "i64.lt_s"), (0x55, "i64.gt_s"), (0x56, "i64.gt_u"),
+        (0x6A, "i32.add"), (0x6B, "i32.sub"), (0x6C, "i32.mul"), (0x71, "i32.and"), (0x72, "i32.or"), (0x74, "i32.shl"), (0x76, "i32.shr_u"),
+        (0x7C, "i64.add"), (0x7D, "i64.sub"), (0x7E, "i64.mul"), (0x88, "i64.shr_u"), (0xA7, "i32.wrap_i64"), (0xAD, "i64.extend_i32_u"),
+    ];
+
+    fn read_unsigned(bytes: &[u8], at: &mut usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = bytes[*at];
+            *at += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+        }
+    }
+
+    fn read_signed(bytes: &[u8], at: &mut usize) -> i64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = bytes[*at];
+            *at += 1;
+            value |= ((byte & 0x7F) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                //the sign is the highest bit read
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return value;
+            }
+        }
+    }
+
+    //reads a vector of value types as the text format names them
+    fn read_types(bytes: &[u8], at: &mut usize) -> Vec<&'static str> {
+        let length = read_unsigned(bytes, at);
+        (0..length).map(|_| {
+            *at += 1;
+            match bytes[*at - 1] {
+                0x7F => "i32",
+                0x7E => "i64",
+                other => panic!("unexpected value type {:#x}", other)
+            }
+        }).collect()
+    }
+
+    //the functions of the binary module written like the text writes them,
+    //with the names the text gives the functions and globals
+    fn read_functions(module: &[u8], functions: &[String], globals: &[String]) -> Vec<String> {
+        let mut sections = [&module[..0]; 12];
+        let mut at = 8;
+        while at < module.len() {
+            let id = module[at] as usize;
+            at += 1;
+            let size = read_unsigned(module, &mut at) as usize;
+            sections[id] = &module[at..at + size];
+            at += size;
+        }
+
+        let (types, mut at) = (sections[1], 0);
+        let signatures: Vec<(Vec<&str>, Vec<&str>)> = (0..read_unsigned(types, &mut at)).map(|_| {
+            assert_eq!(types[at], 0x60);
+            at += 1;
+            (read_types(types, &mut at), read_types(types, &mut at))
+        }).collect();
+
+        let (declarations, mut at) = (sections[3], 0);
+        let declared: Vec<usize> = (0..read_unsigned(declarations, &mut at)).map(|_| read_unsigned(declarations, &mut at) as usize).collect();
+
+        let (exports, mut at) = (sections[7], 0);
+        let mut exported = None;
+        for _ in 0..read_unsigned(exports, &mut at) {
+            let length = read_unsigned(exports, &mut at) as usize;
+            let name = String::from_utf8(exports[at..at + length].to_vec()).unwrap();
+            at += length + 1;
+            let index = read_unsigned(exports, &mut at) as usize;
+            if exports[at - 2] == 0x00 {
+                exported = Some((index, name));
+            }
+        }
+
+        let (code, mut at) = (sections[10], 0);
+        assert_eq!(read_unsigned(code, &mut at) as usize, declared.len());
+        let mut out = Vec::new();
+        for (index, signature) in declared.iter().enumerate() {
+            let index = index + 2;
+            let size = read_unsigned(code, &mut at) as usize;
+            let end = at + size;
+
+            let mut locals = Vec::new();
+            for _ in 0..read_unsigned(code, &mut at) {
+                let count = read_unsigned(code, &mut at);
+                let kind = read_types(&[1, code[at]], &mut 0)[0];
+                at += 1;
+                locals.extend((0..count).map(|_| kind));
+            }
+
+            let mut header = format!("  (func ${}", functions[index]);
+            if let Some((_, name)) = exported.as_ref().filter(|(function, _)| *function == index) {
+                header.push_str(&format!(" (export \"{}\")", name));
+            }
+            let (params, results) = &signatures[*signature];
+            for (name, types) in [("param", params), ("result", results), ("local", &locals)] {
+                if !types.is_empty() {
+                    header.push_str(&format!(" ({} {})", name, types.join(" ")));
+                }
+            }
+            out.push(header);
+
+            let mut depth = 0;
+            loop {
+                let opcode = code[at];
+                at += 1;
+                let line = if opcode == 0xFC {
+                    match read_unsigned(code, &mut at) {
+                        10 => {
+                            assert_eq!(&code[at..at + 2], [0, 0]);
+                            at += 2;
+                            String::from("memory.copy")
+                        },
+                        11 => {
+                            assert_eq!(code[at], 0);
+                            at += 1;
+                            String::from("memory.fill")
+                        },
+                        other => panic!("unexpected 0xFC {}", other)
+                    }
+                }
+                else {
+                    let name = OPCODES.iter().find(|(known, _)| *known == opcode).unwrap_or_else(|| panic!("unexpected opcode {:#x}", opcode)).1;
+                    match opcode {
+                        0x02..=0x04 => {
+                            assert_eq!(code[at], 0x40, "{} with a result", name);
+                            at += 1;
+                        },
+                        0x0B if depth == 0 => break,
+                        _ => ()
+                    }
+                    match opcode {
+                        0x02..=0x04 => depth += 1,
+                        0x0B => depth -= 1,
+                        _ => ()
+                    }
+                    match opcode {
+                        0x0C | 0x0D | 0x20..=0x22 => format!("{} {}", name, read_unsigned(code, &mut at)),
+                        0x10 => format!("call ${}", functions[read_unsigned(code, &mut at) as usize]),
+                        0x23 | 0x24 => format!("{} ${}", name, globals[read_unsigned(code, &mut at) as usize]),
+                        0x41 | 0x42 => format!("{} {}", name, read_signed(code, &mut at)),
+                        0x28..=0x3E => {
+                            //the text leaves out the alignment, so it must be the natural one
+                            let natural = if name.contains('8') { 0 } else if name.contains("16") { 1 } else if name.contains("32") || name.starts_with("i32") { 2 } else { 3 };
+                            assert_eq!(read_unsigned(code, &mut at), natural, "alignment of {}", name);
+                            assert_eq!(read_unsigned(code, &mut at), 0, "offset of {}", name);
+                            String::from(name)
+                        },
+                        0x3F | 0x40 => {
+                            assert_eq!(code[at], 0);
+                            at += 1;
+                            String::from(name)
+                        },
+                        _ => String::from(name)
+                    }
+                };
+                out.push(line);
+            }
+            assert_eq!(at, end, "the body of ${} has bytes after its end", functions[index]);
+            out.push(String::from("  )"));
+        }
+        out
+    }
+
+    #[test]
+    fn text_matches_the_binary() {
+        for (source, config) in programs() {
+            let nodes = optimize(&parse(lex(source)), &config.optimizations);
+            let text = to_wat(&nodes, &config);
+
+            let mut depth = 0;
+            let mut quoted = false;
+            for c in text.chars() {
+                match c {
+                    '"' => quoted = !quoted,
+                    '(' if !quoted => depth += 1,
+                    ')' if !quoted => depth -= 1,
+                    _ => ()
+                }
+                assert!(depth >= 0, "unbalanced parentheses in\n{}", text);
+            }
+            assert_eq!(depth, 0, "unbalanced parentheses in\n{}", text);
+
+            let named = |prefix: &str| -> Vec<String> {
+                text.lines().filter_map(|line| line.split(prefix).nth(1)).map(|rest| rest.split([' ', ')']).next().unwrap().to_string()).collect()
+            };
+            let functions = named("(func $");
+            let globals = named("(global $");
+
+            //everything from the first defined function up to the module's )
+            let lines: Vec<String> = text.lines().skip_while(|line| !line.starts_with("  (func")).map(|line| line.trim_start().to_string()).collect();
+            let decoded: Vec<String> = read_functions(&to_wasm(&nodes, &config), &functions, &globals).iter().map(|line| line.trim_start().to_string()).collect();
+
+            assert_eq!(lines[..lines.len() - 1], decoded[..], "{}", source);
+        }
+    }
+
+    //assembles the text with wat2wasm, which also validates it, None if there
+    //is no wat2wasm
+    fn assemble_with_wat2wasm(text: &str) -> Option<Result<(), String>> {
+        use std::env;
+        use std::fs;
+        use std::process::{self, Command};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static MODULES: AtomicUsize = AtomicUsize::new(0);
+
+        let number = MODULES.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("bf-wat-{}-{}.wat", process::id(), number));
+        let binary = path.with_extension("wasm");
+        fs::write(&path, text).unwrap();
+
+        let output = Command::new("wat2wasm").arg(&path).arg("-o").arg(&binary).output();
+        fs::remove_file(&path).unwrap();
+        let _ = fs::remove_file(&binary);
+        match output {
+            Ok(output) if output.status.success() => Some(Ok(())),
+            Ok(output) => Some(Err(String::from_utf8_lossy(&output.stderr).into_owned())),
+            Err(_) => {
+                println!("No wat2wasm, skipping.");
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn text_assembles() {
+        for (source, config) in programs() {
+            let text = to_wat(&optimize(&parse(lex(source)), &config.optimizations), &config);
+            match assemble_with_wat2wasm(&text) {
+                Some(result) => assert_eq!(result, Ok(()), "{}", text),
+                None => return
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_numbers() {
+        let mut out = Vec::new();
+        unsigned(&mut out, 624485);
+        signed(&mut out, -123456);
+        signed(&mut out, 63);
+        signed(&mut out, 64);
+
+        assert_eq!(out, [0xE5, 0x8E, 0x26, 0xC0, 0xBB, 0x78, 0x3F, 0xC0, 0x00]);
+    }
+
+    #[test]
+    fn imports_and_exports() {
+        let nodes = optimize(&parse(lex("+[,.]")), &Optimizations::default());
+        let wrapping = Config{overflow: Overflow::Wrap, ..Config::default()};
+        let text = to_wat(&nodes, &wrapping);
+
+        assert!(text.contains("(import \"env\" \"read\" (func $read (result i32)))"));
+        assert!(text.contains("(import \"env\" \"write\" (func $write (param i32)))"));
+        assert!(text.contains("(memory (export \"memory\") 1)"));
+        assert!(text.contains("(func $run (export \"run\") (result i32) (local i32 i32)"));
+        //nothing can overflow, so there is no helper for it
+        assert!(!text.contains("$add"));
+
+        assert!(to_wasm(&nodes, &wrapping).starts_with(b"\0asm\x01\0\0\0"));
+    }
+
+    #[test]
+    fn hello_world() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        for optimizations in [Optimizations::default(), Optimizations::none()] {
+            let config = Config{optimizations, ..Config::default()};
+            if let Some((output, stderr, status)) = run_in_node(source, &config, b"") {
+                assert_eq!(output, b"Hello World!\n", "{}", stderr);
+                assert_eq!(status, Some(0));
+            }
+        }
+    }
+
+    #[test]
+    fn honours_the_policies() {
+        let cat = Config{eof: Eof::Zero, ..Config::default()};
+        if let Some((output, _, _)) = run_in_node(",[.,]", &cat, b"cat") {
+            assert_eq!(output, b"cat");
+        }
+
+        let minus_one = Config{eof: Eof::MinusOne, overflow: Overflow::Wrap, cell_type: CellType::U16, ..Config::default()};
+        if let Some((output, _, _)) = run_in_node(",+[-.,+]", &minus_one, b"ab") {
+            assert_eq!(output, b"ab");
+        }
+
+        let error = Config{overflow: Overflow::Error, ..Config::default()};
+        if let Some((_, stderr, status)) = run_in_node("+ -- +", &error, b"") {
+            assert_eq!(stderr, "Error: Subtraction underflow at index 3\n");
+            assert_eq!(status, Some(1));
+        }
+
+        let saturate = Config{overflow: Overflow::Saturate, cell_type: CellType::I8, ..Config::default()};
+        if let Some((output, _, _)) = run_in_node(&format!("-.{}.", "+".repeat(300)), &saturate, b"") {
+            assert_eq!(output, [0xFF, 0x7F]);
+        }
+
+        let short = Config{tape_length: 3, ..Config::default()};
+        if let Some((_, stderr, status)) = run_in_node(">> >", &short, b"") {
+            assert_eq!(stderr, "Error: Data pointer out of bounds at index 3\n");
+            assert_eq!(status, Some(1));
+        }
+
+        let growing = Config{tape_length: 1, growth: TapeGrowth::Both, ..Config::default()};
+        if let Some((output, _, status)) = run_in_node("+++[<<<+++>>>-]<<<[>>>>>+<<<<<-]>>>>>.", &growing, b"") {
+            assert_eq!(output, [9]);
+            assert_eq!(status, Some(0));
+        }
+    }
+}
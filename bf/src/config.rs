@@ -0,0 +1,217 @@
+use crate::optimizer::Optimizations;
+
+use std::str::FromStr;
+
+/// Number of cells on the tape of the original Brainfuck implementation.
+pub const DEFAULT_TAPE_LENGTH: usize = 30_000;
+
+/// What happens when `+` or `-` would take a cell past its range.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Stop the program with "Addition overflow"/"Subtraction underflow".
+    Error,
+    /// Wrap around modulo the cell size, so `-` on 0 gives 255.
+    Wrap,
+    /// Stay at the smallest or largest value.
+    Saturate,
+}
+
+impl FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Overflow, String> {
+        match name {
+            "error" => Ok(Overflow::Error),
+            "wrap" => Ok(Overflow::Wrap),
+            "saturate" => Ok(Overflow::Saturate),
+            _ => Err(format!("unknown overflow policy '{}', expected error, wrap or saturate", name))
+        }
+    }
+}
+
+/// What happens when the data pointer walks off the tape.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TapeGrowth {
+    /// The tape keeps its length and leaving it is an error.
+    Fixed,
+    /// Cells are added on demand to the right. Moving left of cell 0 is
+    /// still an error.
+    Right,
+    /// Cells are added on demand on both sides, so indices can go negative.
+    Both,
+}
+
+impl FromStr for TapeGrowth {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<TapeGrowth, String> {
+        match name {
+            "fixed" => Ok(TapeGrowth::Fixed),
+            "right" => Ok(TapeGrowth::Right),
+            "both" => Ok(TapeGrowth::Both),
+            _ => Err(format!("unknown tape growth '{}', expected fixed, right or both", name))
+        }
+    }
+}
+
+/// What `,` does when there is no input left.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Eof {
+    /// Stop the program with "End of input".
+    Error,
+    /// Leave the cell as it was.
+    Unchanged,
+    /// Set the cell to 0.
+    Zero,
+    /// Set the cell to -1, which is the largest value of an unsigned cell.
+    MinusOne,
+}
+
+impl FromStr for Eof {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Eof, String> {
+        match name {
+            "error" => Ok(Eof::Error),
+            "unchanged" => Ok(Eof::Unchanged),
+            "zero" => Ok(Eof::Zero),
+            "minus-one" => Ok(Eof::MinusOne),
+            _ => Err(format!("unknown end of input policy '{}', expected error, unchanged, zero or minus-one", name))
+        }
+    }
+}
+
+/// Size and signedness of a tape cell.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CellType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+}
+
+impl CellType {
+    pub fn bits(self) -> u32 {
+        match self {
+            CellType::U8 | CellType::I8 => 8,
+            CellType::U16 | CellType::I16 => 16,
+            CellType::U32 | CellType::I32 => 32,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(self, CellType::I8 | CellType::I16 | CellType::I32)
+    }
+
+    /// Smallest value a cell can hold.
+    pub fn min(self) -> i64 {
+        if self.is_signed() {
+            -(1 << (self.bits() - 1))
+        }
+        else {
+            0
+        }
+    }
+
+    /// Largest value a cell can hold.
+    pub fn max(self) -> i64 {
+        self.min() + (1 << self.bits()) - 1
+    }
+
+    /// Brings any value into range the way the hardware type would, so 256
+    /// is 0 for `U8` and 255 is -1 for `I8`.
+    pub fn wrap(self, value: i64) -> i64 {
+        self.min() + (value - self.min()).rem_euclid(1 << self.bits())
+    }
+
+    /// The byte written by `.`, which is the lowest 8 bits of the cell.
+    pub fn to_byte(self, value: i64) -> u8 {
+        value.rem_euclid(256) as u8
+    }
+}
+
+impl FromStr for CellType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<CellType, String> {
+        match name {
+            "u8" => Ok(CellType::U8),
+            "i8" => Ok(CellType::I8),
+            "u16" => Ok(CellType::U16),
+            "i16" => Ok(CellType::I16),
+            "u32" => Ok(CellType::U32),
+            "i32" => Ok(CellType::I32),
+            _ => Err(format!("unknown cell type '{}', expected u8, i8, u16, i16, u32 or i32", name))
+        }
+    }
+}
+
+/// Settings for a single run.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct Config {
+    /// Number of cells on the tape. When the tape can grow this is only
+    /// how many cells it starts with.
+    pub tape_length: usize,
+    pub growth: TapeGrowth,
+    pub overflow: Overflow,
+    pub cell_type: CellType,
+    pub eof: Eof,
+    pub optimizations: Optimizations,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            tape_length: DEFAULT_TAPE_LENGTH,
+            growth: TapeGrowth::Fixed,
+            overflow: Overflow::Error,
+            cell_type: CellType::U8,
+            eof: Eof::Unchanged,
+            optimizations: Optimizations::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_ranges() {
+        assert_eq!((CellType::U8.min(), CellType::U8.max()), (0, 255));
+        assert_eq!((CellType::I8.min(), CellType::I8.max()), (-128, 127));
+        assert_eq!((CellType::U16.min(), CellType::U16.max()), (0, 65535));
+        assert_eq!((CellType::I32.min(), CellType::I32.max()), (i32::MIN as i64, i32::MAX as i64));
+    }
+
+    #[test]
+    fn wrapping_into_range() {
+        assert_eq!(CellType::U8.wrap(256), 0);
+        assert_eq!(CellType::U8.wrap(-1), 255);
+        assert_eq!(CellType::I8.wrap(255), -1);
+        assert_eq!(CellType::I8.wrap(128), -128);
+        assert_eq!(CellType::U32.wrap(-1), u32::MAX as i64);
+    }
+
+    #[test]
+    fn output_byte() {
+        assert_eq!(CellType::I8.to_byte(-1), 255);
+        assert_eq!(CellType::U16.to_byte(0x141), b'A');
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!("saturate".parse(), Ok(Overflow::Saturate));
+        assert_eq!("both".parse(), Ok(TapeGrowth::Both));
+        assert_eq!("i16".parse(), Ok(CellType::I16));
+        assert_eq!("minus-one".parse(), Ok(Eof::MinusOne));
+        assert!("u64".parse::<CellType>().is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use crate::tape::Tape;
+use crate::watch::{Watch, Watcher};
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
+use std::{thread, time};
+
+#[derive(Debug)]
+#[derive(Default)]
+struct State {
+    cancelled: AtomicBool,
+    paused: AtomicBool,
+    delay: AtomicU16,
+    steps: AtomicUsize,
+    //text offsets to stop at, and whether there are any so runs without
+    //breakpoints do not lock for every instruction
+    breakpoints: Mutex<BTreeSet<usize>>,
+    has_breakpoints: AtomicBool,
+    //a breakpoint that goes away once it is reached, for Command::RunTo;
+    //always locked after breakpoints
+    run_to: Mutex<Option<usize>>,
+    watchers: Mutex<Vec<Watcher>>,
+    has_watches: AtomicBool,
+    //how many steps run keeps to undo, how many it has, and how many it
+    //was asked to undo
+    history_limit: AtomicUsize,
+    history: AtomicUsize,
+    back: AtomicUsize,
+    //a step over, through or out of a loop the paused program was asked for
+    stepping: Mutex<Option<Stepping>>,
+    idiom_labels: AtomicBool,
+}
+
+//how far a paused program runs before it pauses again, in terms of the
+//innermost loop around the instruction it was paused at
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stepping {
+    //to the next instruction outside any loop entered on the way
+    Over,
+    //past the ] of the loop, into its next iteration or out of it
+    Iteration,
+    //until the loop is left
+    Out,
+}
+
+//what a run does once it may go on
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Proceed {
+    Run,
+    Back(usize),
+    Until(Stepping),
+    Stop,
+}
+
+/// Something the driver of a program asks of it. Commands take effect in
+/// the order they are sent, and the ones that let a paused program go on
+/// say exactly how far it goes.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Lets a paused program execute exactly this many more instructions.
+    Step(usize),
+    StepOver,
+    FinishIteration,
+    StepOut,
+    /// Undoes this many instructions of a paused program.
+    StepBack(usize),
+    /// Lets the program run until it reaches the instruction made from the
+    /// character at this text offset, and pauses it before that instruction.
+    RunTo(usize),
+    Pause,
+    Resume,
+    Cancel,
+}
+
+/// Handle used to pause, step, slow down or cancel a single run.
+///
+/// Clones share the same state, so one clone can be given to `run` while
+/// another one stays with whoever drives the program (a GUI, a test, ...).
+/// Every run should get its own controller.
+#[derive(Debug)]
+#[derive(Clone, Default)]
+pub struct ExecutionController {
+    state: Arc<State>,
+}
+
+impl ExecutionController {
+    /// A controller that runs at full speed and is not paused.
+    pub fn new() -> ExecutionController {
+        ExecutionController::default()
+    }
+
+    /// Does what `command` asks, see the methods of the same names.
+    pub fn send(&self, command: Command) {
+        match command {
+            Command::Step(steps) => self.step_by(steps),
+            Command::StepOver => self.step_over(),
+            Command::FinishIteration => self.finish_iteration(),
+            Command::StepOut => self.step_out(),
+            Command::StepBack(steps) => self.step_back(steps),
+            Command::RunTo(offset) => self.run_to(offset),
+            Command::Pause => self.pause(),
+            Command::Resume => self.resume(),
+            Command::Cancel => self.cancel(),
+        }
+    }
+
+    /// Suspends the program before its next instruction. Steps it was let
+    /// take and has not taken yet are dropped, so after a breakpoint or
+    /// watch stopped a `Command::Step` the next step is exactly one.
+    pub fn pause(&self) {
+        self.state.steps.store(0, Ordering::Relaxed);
+        self.state.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Lets a paused program continue.
+    pub fn resume(&self) {
+        self.state.steps.store(0, Ordering::Relaxed);
+        self.state.back.store(0, Ordering::Relaxed);
+        *self.state.stepping.lock().unwrap() = None;
+        self.state.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::Relaxed)
+    }
+
+    /// Lets a paused program execute exactly one more instruction. Does
+    /// nothing if the program is not paused.
+    pub fn step(&self) {
+        self.step_by(1);
+    }
+
+    /// Lets a paused program execute exactly `steps` more instructions.
+    /// Does nothing if the program is not paused.
+    pub fn step_by(&self, steps: usize) {
+        if self.is_paused() {
+            self.state.steps.fetch_add(steps, Ordering::Relaxed);
+        }
+    }
+
+    //how many more instructions the paused program may execute
+    #[cfg(test)]
+    pub(crate) fn steps_left(&self) -> usize {
+        self.state.steps.load(Ordering::Relaxed)
+    }
+
+    /// Lets the program run until it reaches the instruction made from the
+    /// character at `offset`, where it pauses as if at a breakpoint. A
+    /// program that never gets there runs to its end.
+    pub fn run_to(&self, offset: usize) {
+        let breakpoints = self.state.breakpoints.lock().unwrap();
+        *self.state.run_to.lock().unwrap() = Some(offset);
+        self.state.has_breakpoints.store(true, Ordering::Relaxed);
+        drop(breakpoints);
+
+        self.resume();
+    }
+
+    /// Lets a paused program execute its next instruction, and if that
+    /// enters a loop, also the rest of the loop. Does nothing if the program
+    /// is not paused.
+    pub fn step_over(&self) {
+        self.step_until(Stepping::Over);
+    }
+
+    /// Lets a paused program run to the end of the current iteration of the
+    /// innermost loop it is in, and pauses it again at the start of the next
+    /// iteration or after the loop. Does nothing if the program is not
+    /// paused.
+    pub fn finish_iteration(&self) {
+        self.step_until(Stepping::Iteration);
+    }
+
+    /// Lets a paused program run until it leaves the innermost loop it is
+    /// in, or to the end outside of loops. Does nothing if the program is not
+    /// paused.
+    pub fn step_out(&self) {
+        self.step_until(Stepping::Out);
+    }
+
+    fn step_until(&self, stepping: Stepping) {
+        if self.is_paused() {
+            *self.state.stepping.lock().unwrap() = Some(stepping);
+        }
+    }
+
+    /// Makes `run` keep the last `steps` instructions it executed so they can
+    /// be undone with `step_back`. The default is 0, which keeps none.
+    pub fn set_history_limit(&self, steps: usize) {
+        self.state.history_limit.store(steps, Ordering::Relaxed);
+    }
+
+    pub fn history_limit(&self) -> usize {
+        self.state.history_limit.load(Ordering::Relaxed)
+    }
+
+    /// How many instructions a paused program can currently step back.
+    pub fn history_len(&self) -> usize {
+        self.state.history.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_history_len(&self, steps: usize) {
+        self.state.history.store(steps, Ordering::Relaxed);
+    }
+
+    /// Lets a paused program undo its last `steps` instructions, or as many
+    /// as it kept. Stepping forward again runs them again, with the same
+    /// input. Does nothing if the program is not paused.
+    pub fn step_back(&self, steps: usize) {
+        if self.is_paused() {
+            self.state.back.fetch_add(steps, Ordering::Relaxed);
+        }
+    }
+
+    /// Stops the program. A cancelled run cannot be resumed.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Sets how many milliseconds to wait before every instruction.
+    pub fn set_delay(&self, millis: u16) {
+        self.state.delay.store(millis, Ordering::Relaxed);
+    }
+
+    pub fn delay(&self) -> u16 {
+        self.state.delay.load(Ordering::Relaxed)
+    }
+
+    /// Makes `run` report the idioms it runs as `Action::Idiom`, with a text
+    /// that says what they did. By default they are reported as
+    /// `Action::Tape`, without the cost of the text.
+    pub fn set_idiom_labels(&self, labels: bool) {
+        self.state.idiom_labels.store(labels, Ordering::Relaxed);
+    }
+
+    pub fn idiom_labels(&self) -> bool {
+        self.state.idiom_labels.load(Ordering::Relaxed)
+    }
+
+    /// Pauses the program before every instruction made from the character
+    /// at `offset`. A `#` in the source only stops the program if it has a
+    /// breakpoint too.
+    pub fn add_breakpoint(&self, offset: usize) {
+        let mut breakpoints = self.state.breakpoints.lock().unwrap();
+        breakpoints.insert(offset);
+        self.state.has_breakpoints.store(true, Ordering::Relaxed);
+    }
+
+    pub fn remove_breakpoint(&self, offset: usize) {
+        let mut breakpoints = self.state.breakpoints.lock().unwrap();
+        breakpoints.remove(&offset);
+        self.update_has_breakpoints(&breakpoints);
+    }
+
+    /// Replaces every breakpoint with the ones at `offsets`. This can be done
+    /// while the program runs.
+    pub fn set_breakpoints<I: IntoIterator<Item = usize>>(&self, offsets: I) {
+        let mut breakpoints = self.state.breakpoints.lock().unwrap();
+        *breakpoints = offsets.into_iter().collect();
+        self.update_has_breakpoints(&breakpoints);
+    }
+
+    fn update_has_breakpoints(&self, breakpoints: &BTreeSet<usize>) {
+        let run_to = self.state.run_to.lock().unwrap();
+        self.state.has_breakpoints.store(!breakpoints.is_empty() || run_to.is_some(), Ordering::Relaxed);
+    }
+
+    /// Text offsets of every breakpoint, in order.
+    pub fn breakpoints(&self) -> Vec<usize> {
+        self.state.breakpoints.lock().unwrap().iter().copied().collect()
+    }
+
+    //whether an instruction made from the characters at offsets has a
+    //breakpoint, or is where a run to stops
+    pub(crate) fn breaks_at(&self, offsets: &[usize]) -> bool {
+        if !self.state.has_breakpoints.load(Ordering::Relaxed) {
+            return false;
+        }
+        let breakpoints = self.state.breakpoints.lock().unwrap();
+
+        let mut run_to = self.state.run_to.lock().unwrap();
+        let reached = run_to.is_some_and(|target| offsets.contains(&target));
+        if reached {
+            *run_to = None;
+            self.state.has_breakpoints.store(!breakpoints.is_empty(), Ordering::Relaxed);
+        }
+
+        reached || offsets.iter().any(|offset| breakpoints.contains(offset))
+    }
+
+    /// Replaces every watch. A watch starts from the tape as it is at the
+    /// next check, so a condition that already holds then only stops the
+    /// program once it stopped holding and holds again.
+    pub fn set_watches<I: IntoIterator<Item = Watch>>(&self, watches: I) {
+        let mut watchers = self.state.watchers.lock().unwrap();
+        *watchers = watches.into_iter().map(Watcher::new).collect();
+        self.state.has_watches.store(!watchers.is_empty(), Ordering::Relaxed);
+    }
+
+    pub fn watches(&self) -> Vec<Watch> {
+        self.state.watchers.lock().unwrap().iter().map(|watcher| watcher.watch).collect()
+    }
+
+    //checks every watch after the cells at written were written, and says
+    //what triggered if any did
+    pub(crate) fn watch(&self, tape: &Tape, written: &[isize]) -> Option<String> {
+        if !self.state.has_watches.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        //every watcher sees the tape, even after one of them triggered
+        let triggered: Vec<String> = self.state.watchers.lock().unwrap().iter_mut()
+            .filter_map(|watcher| watcher.check(tape, written))
+            .collect();
+
+        if triggered.is_empty() { None } else { Some(triggered.join(", ")) }
+    }
+
+    //blocks until the next instruction may run, or the program should step
+    //back or run until it is out of a loop; the run resumes for the latter
+    pub(crate) fn proceed(&self) -> Proceed {
+        while self.is_paused() {
+            if self.is_cancelled() {
+                return Proceed::Stop;
+            }
+            let back = self.state.back.swap(0, Ordering::Relaxed);
+            if back > 0 {
+                return Proceed::Back(back);
+            }
+            let stepping = self.state.stepping.lock().unwrap().take();
+            if let Some(stepping) = stepping {
+                self.resume();
+                return Proceed::Until(stepping);
+            }
+            let steps = self.state.steps.load(Ordering::Relaxed);
+            if steps > 0 && self.state.steps.compare_exchange(steps, steps - 1, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(1));
+        }
+
+        let delay = self.delay();
+        if delay > 0 {
+            thread::sleep(time::Duration::from_millis(delay as u64));
+        }
+
+        if self.is_cancelled() { Proceed::Stop } else { Proceed::Run }
+    }
+}
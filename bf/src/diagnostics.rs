@@ -0,0 +1,105 @@
+use crate::lexer::{lex, OpCode};
+use crate::span::{SourceMap, Span};
+
+use std::fmt;
+
+/// A problem in the source that keeps it from running.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Where the problem is in the source.
+    pub span: Span,
+    /// Line of the problem, counted from 1.
+    pub line: usize,
+    /// Column of the problem in characters, counted from 1.
+    pub column: usize,
+    pub message: String,
+    /// The line with the problem and a caret under it, both indented.
+    pub snippet: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {}, column {}\n{}", self.message, self.line, self.column, self.snippet)
+    }
+}
+
+/// Finds every `[` without a matching `]` and every `]` without a matching
+/// `[`, in source order.
+///
+/// `parse` turns only the first of them into an error, and only when
+/// execution gets there, so front ends should check the source first.
+pub fn check(source: &str) -> Vec<Diagnostic> {
+    let map = SourceMap::new(source);
+    let mut open = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    for (op, offset) in lex(source) {
+        match op {
+            OpCode::LoopBegin => open.push(offset),
+            OpCode::LoopEnd if open.pop().is_none() => {
+                diagnostics.push(Diagnostic::new(&map, Span::at(offset), "Loop without beginning"));
+            },
+            _ => ()
+        }
+    }
+
+    diagnostics.extend(open.into_iter().map(|offset| Diagnostic::new(&map, Span::at(offset), "Loop without ending")));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    diagnostics
+}
+
+impl Diagnostic {
+    /// A diagnostic for `span`, placed in the source through `map`.
+    pub fn new(map: &SourceMap, span: Span, message: &str) -> Diagnostic {
+        let (line, column) = map.position(span.start);
+
+        Diagnostic {
+            span,
+            line,
+            column,
+            message: String::from(message),
+            snippet: map.snippet(span),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balanced() {
+        assert!(check("+[>[-]<] comment [ ]").is_empty());
+    }
+
+    #[test]
+    fn every_mismatch() {
+        let diagnostics = check("]+[\n[-]\n  [");
+        let found: Vec<(usize, usize, &str)> = diagnostics.iter().map(|d| (d.line, d.column, d.message.as_str())).collect();
+
+        assert_eq!(found, vec![
+            (1, 1, "Loop without beginning"),
+            (1, 3, "Loop without ending"),
+            (3, 3, "Loop without ending"),
+        ]);
+        assert_eq!(diagnostics[2].span, Span::at(10));
+    }
+
+    #[test]
+    fn positions_skip_comments() {
+        let diagnostic = &check("read a byte, then [ loop\n  until zero ]]")[0];
+
+        assert_eq!((diagnostic.span.start, diagnostic.line, diagnostic.column), (39, 2, 15));
+        assert_eq!(diagnostic.to_string(), "Loop without beginning at line 2, column 15\n      until zero ]]\n                  ^");
+    }
+
+    #[test]
+    fn long_lines_are_cut() {
+        let source = "+".repeat(100) + "]" + &"-".repeat(100);
+        let diagnostic = &check(&source)[0];
+
+        assert_eq!(diagnostic.column, 101);
+        assert_eq!(diagnostic.snippet, format!("    {}]{}\n    {}^", "+".repeat(30), "-".repeat(30), " ".repeat(30)));
+    }
+}
@@ -0,0 +1,1201 @@
+use crate::controller::{ExecutionController, Proceed, Stepping};
+use crate::bytecode::{Bytecode, Program};
+use crate::history::{Entry, History};
+use crate::optimizer::Idiom;
+use crate::span::Span;
+use crate::tape::Tape;
+
+use std::sync::mpsc::{Receiver, Sender};
+
+/// What a `CellChange` is reporting.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum Action {
+    /// The executor is waiting for a value on the input channel.
+    Input,
+    /// The current cell was written to the output.
+    Output,
+    /// The tape or the data pointer changed.
+    Tape,
+    /// A loop recognised as an idiom ran in one step. The text says what
+    /// it did, like "clear cell 3". The cells it changed away from the data
+    /// pointer were reported as `Action::Tape` just before. Only sent if the
+    /// controller asked for it, otherwise the idiom is an `Action::Tape`.
+    Idiom(String),
+    /// The program reached a breakpoint, or the offset of a
+    /// `Command::RunTo`, and was paused before the instruction of the span.
+    Breakpoint,
+    /// A watch of the controller triggered after the instruction of the
+    /// span, and the controller was paused. The text says which one, like
+    /// "cell[3] == 10" or "cell 3 changed to 10".
+    Watch(String),
+    /// The paused program stepped back, and the span is the instruction it
+    /// runs next. Only the given number of values written to the output
+    /// before are still written. The cells it put back away from the data
+    /// pointer were reported as `Action::Tape` just before.
+    Rewound(usize),
+    /// Execution stopped with the given message.
+    Error(String)
+}
+
+/// An event sent by `run` after every executed instruction.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct CellChange{
+    /// Position of the data pointer, negative if the tape grew to the left.
+    pub index: isize,
+    /// Value of the cell under the data pointer.
+    pub content: i64,
+    pub action: Action,
+    /// Source of the instruction that caused the event, which is more than
+    /// one character for folded runs like `+++` and for idioms, and just the
+    /// failing character for errors.
+    pub span: Span
+}
+
+/// Runs the compiled program.
+///
+/// Every executed instruction is reported through `send_cell`. When the
+/// program reads, an `Action::Input` event is sent and the value is then
+/// taken from `receive_data`; `None` means there is no input left, and the
+/// cell is then set following the `Eof` policy of the tape. Values outside
+/// the range of the cell type are wrapped into it.
+///
+/// `controller` is checked before every instruction except jumps, which only
+/// check whether the run was cancelled, so an empty loop like `+[]` can
+/// still be stopped. Errors cancel it. When an instruction has a breakpoint
+/// of the controller, the controller is paused and an `Action::Breakpoint`
+/// is sent first; a `#` with a breakpoint pauses before the instruction
+/// after it. The watches of the controller are checked after every
+/// instruction that writes a cell or moves the data pointer, and pause it
+/// too. With a history limit, the controller can also step a paused
+/// program back, and reads that run again get the input they had before.
+/// A paused program can also step over, through or out of the loop it is
+/// in; it pauses again once there, unless something pauses it first.
+pub fn run(program: &Program, tape: &mut Tape, send_cell: Sender<CellChange>, receive_data: &Receiver<Option<i64>>, controller: &ExecutionController) {
+    let mut position = 0;
+    let mut history = History::default();
+    //how many values were written to the output
+    let mut outputs = 0;
+    //the instruction a rewind went back to does not stop at its breakpoint again
+    let mut rewound = false;
+    //where a step over, through or out of a loop pauses again
+    let mut until: Option<Target> = None;
+    let loops = enclosing_loops(program);
+
+    //lets the watches see the tape as it is before the first instruction
+    controller.watch(tape, &[]);
+
+    while let Some(code) = program.code.get(position) {
+        let span = program.span(position);
+
+        let stops = !rewound && controller.breaks_at(&program.offsets[position]);
+        rewound = false;
+        if stops {
+            controller.pause();
+            send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Breakpoint, span}).unwrap();
+        }
+
+        //jumps wait only at a breakpoint, and a # never does since it would
+        //take a step without anything to show for it
+        let waits = match code {
+            Bytecode::JumpIfZero(_) | Bytecode::JumpUnlessZero(_) => stops,
+            Bytecode::Breakpoint => false,
+            _ => true
+        };
+        if waits {
+            if let Some(target) = &until {
+                //a breakpoint, watch or the user may have paused it first
+                if controller.is_paused() {
+                    until = None;
+                }
+                else if target.reached(loops[position], &loops) {
+                    controller.pause();
+                    until = None;
+                }
+            }
+
+            match controller.proceed() {
+                Proceed::Run => (),
+                Proceed::Until(stepping) => until = Some(Target{stepping, from: loops[position], passed: false}),
+                Proceed::Stop => break,
+                Proceed::Back(steps) => {
+                    if let Some(entry) = rewind(steps, &mut history, tape, program, controller, &send_cell) {
+                        position = entry.position;
+                        outputs = entry.outputs;
+                    }
+                    //the watches start again from the tape as it is now
+                    controller.watch(tape, &[]);
+                    rewound = true;
+                    continue;
+                }
+            }
+        }
+
+        let limit = controller.history_limit();
+        let mut entry = if waits && limit > 0 {
+            Some(Entry{position, pointer: tape.pointer(), cells: cells_written_by(code, tape), outputs, input: None})
+        }
+        else {
+            None
+        };
+
+        position = match code {
+
+            Bytecode::JumpIfZero(after) => {
+                if tape.current() == 0 { *after } else { position + 1 }
+            },
+
+            Bytecode::JumpUnlessZero(body) => {
+                if controller.is_cancelled() {
+                    break;
+                }
+                if let Some(target) = &mut until {
+                    target.passed |= loops[position] == target.from;
+                }
+                if tape.current() != 0 { *body } else { position + 1 }
+            },
+
+            Bytecode::Move(amount) => {
+                if let Err(error) = move_pointer(*amount, &program.offsets[position], tape) {
+                    send_cell.send(error).unwrap();
+                    controller.cancel();
+                    break;
+                }
+                send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Tape, span}).unwrap();
+                check_watches(controller, tape, &[], span, &send_cell);
+                position + 1
+            },
+
+            Bytecode::Add(amount) => {
+                if let Err(error) = add(*amount, &program.offsets[position], tape) {
+                    send_cell.send(error).unwrap();
+                    controller.cancel();
+                    break;
+                }
+                send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Tape, span}).unwrap();
+                check_watches(controller, tape, &[tape.pointer()], span, &send_cell);
+                position + 1
+            },
+
+            Bytecode::Write => {
+                send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Output, span}).unwrap();
+                outputs += 1;
+                position + 1
+            },
+
+            Bytecode::Read => {
+                //a read that runs again after stepping back gets the same input
+                let replayed = history.replayed_input();
+                let value = match replayed {
+                    Some(value) => value,
+                    None => {
+                        send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Input, span}).unwrap();
+                        receive_data.recv().unwrap()
+                    }
+                };
+                if let Some(entry) = &mut entry {
+                    entry.input = Some(value);
+                }
+
+                if let Some(input) = value {
+                    tape.set(input);
+                }
+                else if !tape.end_of_input() {
+                    send_cell.send(end_of_input(tape, span)).unwrap();
+                    controller.cancel();
+                    break;
+                }
+                if replayed.is_some() {
+                    send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Tape, span}).unwrap();
+                }
+                check_watches(controller, tape, &[tape.pointer()], span, &send_cell);
+                position + 1
+            },
+
+            //like a loop, an idiom does nothing on a zero cell
+            Bytecode::Idiom(_, after) if tape.current() == 0 => *after,
+
+            Bytecode::Idiom(Idiom::Scan(step), after) => {
+                let from = tape.pointer();
+                if let Err(error) = scan(*step, program, position, tape) {
+                    send_cell.send(error).unwrap();
+                    controller.cancel();
+                    return;
+                }
+
+                let action = if controller.idiom_labels() {
+                    let direction = if *step > 0 { "right" } else { "left" };
+                    Action::Idiom(format!("scan {} from cell {} to cell {}", direction, from, tape.pointer()))
+                }
+                else {
+                    Action::Tape
+                };
+                send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action, span}).unwrap();
+                check_watches(controller, tape, &[], span, &send_cell);
+                *after
+            },
+
+            //adding in one go only matches the loop when cells wrap, and the
+            //targets have to be on the tape, otherwise the loop runs as written
+            Bytecode::Idiom(idiom, after) if tape.wraps() && reaches_targets(idiom, tape) => {
+                let counter = tape.pointer();
+                run_idiom(idiom, tape);
+                for index in written_by(idiom, counter).into_iter().skip(1) {
+                    send_cell.send(CellChange{index, content: tape.get(index).unwrap_or(0), action: Action::Tape, span}).unwrap();
+                }
+                let action = if controller.idiom_labels() { Action::Idiom(describe_idiom(idiom, counter)) } else { Action::Tape };
+                send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action, span}).unwrap();
+                check_watches(controller, tape, &written_by(idiom, tape.pointer()), span, &send_cell);
+                *after
+            },
+
+            Bytecode::Idiom(..) => position + 1,
+
+            Bytecode::Breakpoint => position + 1,
+
+            Bytecode::Error(e) => {
+                send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Error(e.clone()), span}).unwrap();
+                controller.cancel();
+                break;
+            }
+        };
+
+        if let Some(entry) = entry {
+            history.record(entry, limit);
+            controller.set_history_len(history.len());
+        }
+    }
+}
+
+/// Runs the compiled program like `run`, but only sends the `Action::Input`,
+/// `Action::Output` and `Action::Error` events, for front ends that do not
+/// show the steps. It skips everything else `run` does for every
+/// instruction, which makes it much faster. The controller is only checked
+/// for cancellation, at the end of every pass through a loop, so it cannot
+/// pause the program or stop it at breakpoints and watches.
+pub fn run_headless(program: &Program, tape: &mut Tape, send_cell: Sender<CellChange>, receive_data: &Receiver<Option<i64>>, controller: &ExecutionController) {
+    let mut position = 0;
+
+    while let Some(code) = program.code.get(position) {
+        //where the program goes on, or the error it stopped with
+        let next = match code {
+
+            Bytecode::JumpIfZero(after) => Ok(if tape.current() == 0 { *after } else { position + 1 }),
+
+            Bytecode::JumpUnlessZero(body) => {
+                if controller.is_cancelled() {
+                    break;
+                }
+                Ok(if tape.current() != 0 { *body } else { position + 1 })
+            },
+
+            Bytecode::Move(amount) => move_pointer(*amount, &program.offsets[position], tape).map(|()| position + 1),
+
+            Bytecode::Add(amount) => add(*amount, &program.offsets[position], tape).map(|()| position + 1),
+
+            Bytecode::Write => {
+                send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Output, span: program.span(position)}).unwrap();
+                Ok(position + 1)
+            },
+
+            Bytecode::Read => {
+                let span = program.span(position);
+                send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Input, span}).unwrap();
+
+                match receive_data.recv().unwrap() {
+                    Some(input) => {
+                        tape.set(input);
+                        Ok(position + 1)
+                    },
+                    None if tape.end_of_input() => Ok(position + 1),
+                    None => Err(end_of_input(tape, span))
+                }
+            },
+
+            Bytecode::Idiom(_, after) if tape.current() == 0 => Ok(*after),
+
+            Bytecode::Idiom(Idiom::Scan(step), after) => scan(*step, program, position, tape).map(|()| *after),
+
+            //as in run, otherwise the loop runs as written
+            Bytecode::Idiom(idiom, after) if tape.wraps() && reaches_targets(idiom, tape) => {
+                run_idiom(idiom, tape);
+                Ok(*after)
+            },
+
+            Bytecode::Idiom(..) | Bytecode::Breakpoint => Ok(position + 1),
+
+            Bytecode::Error(e) => Err(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Error(e.clone()), span: program.span(position)})
+        };
+
+        match next {
+            Ok(next) => position = next,
+            Err(error) => {
+                send_cell.send(error).unwrap();
+                controller.cancel();
+                break;
+            }
+        }
+    }
+}
+
+//a step over, through or out of the loop that starts at from, None
+//outside of loops
+struct Target {
+    stepping: Stepping,
+    from: Option<usize>,
+    //whether the ] of that loop ran
+    passed: bool,
+}
+
+impl Target {
+    //whether an instruction in the loop at the given start is where the
+    //program pauses again
+    fn reached(&self, inside: Option<usize>, loops: &[Option<usize>]) -> bool {
+        let around = encloses(inside, self.from, loops);
+
+        match self.stepping {
+            Stepping::Over => around,
+            Stepping::Iteration => around && self.passed,
+            Stepping::Out => around && inside != self.from,
+        }
+    }
+}
+
+//the position of the [ of the innermost loop around every instruction, a [
+//is still outside its loop and a ] inside it
+fn enclosing_loops(program: &Program) -> Vec<Option<usize>> {
+    let mut open = Vec::new();
+
+    program.code.iter().enumerate().map(|(position, code)| {
+        let inside = open.last().copied();
+        match code {
+            Bytecode::JumpIfZero(_) => open.push(position),
+            Bytecode::JumpUnlessZero(_) => return open.pop(),
+            _ => ()
+        }
+        inside
+    }).collect()
+}
+
+//whether the loop at outer is the loop at inner or around it
+fn encloses(outer: Option<usize>, inner: Option<usize>, loops: &[Option<usize>]) -> bool {
+    let mut current = inner;
+    loop {
+        if current == outer {
+            return true;
+        }
+        match current {
+            Some(begin) => current = loops[begin],
+            None => return false
+        }
+    }
+}
+
+//the cells the instruction may write, with their values before it
+fn cells_written_by(code: &Bytecode, tape: &Tape) -> Vec<(isize, i64)> {
+    let written = match code {
+        Bytecode::Add(_) | Bytecode::Read => vec![tape.pointer()],
+        Bytecode::Idiom(Idiom::Scan(_), _) => Vec::new(),
+        //only an idiom that runs in one go writes, see run, a skipped one
+        //writes nothing and one that runs as a loop leaves it to its body
+        Bytecode::Idiom(idiom, _) if tape.current() != 0 && tape.wraps() && reaches_targets(idiom, tape) => written_by(idiom, tape.pointer()),
+        _ => Vec::new()
+    };
+
+    //cells a growing tape has not reached yet are 0
+    written.into_iter().map(|index| (index, tape.get(index).unwrap_or(0))).collect()
+}
+
+//undoes up to steps instructions and reports the cells it put back, the
+//last entry undone says where the program goes on from
+fn rewind(steps: usize, history: &mut History, tape: &mut Tape, program: &Program, controller: &ExecutionController, send_cell: &Sender<CellChange>) -> Option<Entry> {
+    let mut restored = Vec::new();
+    let mut last = None;
+
+    for _ in 0..steps {
+        match history.undo(tape) {
+            Some(entry) => {
+                restored.extend(entry.cells.iter().map(|&(index, _)| index));
+                last = Some(entry);
+            },
+            None => break
+        }
+    }
+    controller.set_history_len(history.len());
+
+    let entry = last?;
+    let span = program.span(entry.position);
+
+    restored.sort_unstable();
+    restored.dedup();
+    for index in restored.into_iter().filter(|&index| index != tape.pointer()) {
+        send_cell.send(CellChange{index, content: tape.get(index).unwrap_or(0), action: Action::Tape, span}).unwrap();
+    }
+    send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Rewound(entry.outputs), span}).unwrap();
+
+    Some(entry)
+}
+
+//moves one cell at a time, so an error points at the character that left the tape
+fn move_pointer(amount: isize, offsets: &[usize], tape: &mut Tape) -> Result<(), CellChange> {
+    for &offset in offsets {
+        if !tape.move_by(amount.signum()) {
+            let message = format!("Data pointer out of bounds at index {}", offset);
+            return Err(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Error(message), span: Span::at(offset)});
+        }
+    }
+    Ok(())
+}
+
+//adds a folded run of + or - to the current cell, an error points at the
+//character after the last one that fit
+fn add(amount: i64, offsets: &[usize], tape: &mut Tape) -> Result<(), CellChange> {
+    let before = tape.current();
+
+    if !tape.add(amount) {
+        //the cell stopped at its limit
+        let offset = offsets[(tape.current() - before).unsigned_abs() as usize];
+        let message = if amount > 0 { "Addition overflow" } else { "Subtraction underflow" };
+        return Err(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Error(format!("{} at index {}", message, offset)), span: Span::at(offset)});
+    }
+    Ok(())
+}
+
+//the error of a , with no input left under Eof::Error
+fn end_of_input(tape: &Tape, span: Span) -> CellChange {
+    CellChange{index: tape.pointer(), content: tape.current(), action: Action::Error(format!("End of input at index {}", span.start)), span}
+}
+
+//runs the scan idiom at position, a cell that is not 0 to start with
+fn scan(step: isize, program: &Program, position: usize, tape: &mut Tape) -> Result<(), CellChange> {
+    //the move is the body of the loop that follows the idiom
+    let offsets = &program.offsets[position + 2];
+
+    while tape.current() != 0 {
+        move_pointer(step, offsets, tape)?;
+    }
+    Ok(())
+}
+
+//pauses the program when one of the watches of the controller triggered
+fn check_watches(controller: &ExecutionController, tape: &Tape, written: &[isize], span: Span, send_cell: &Sender<CellChange>) {
+    if let Some(label) = controller.watch(tape, written) {
+        controller.pause();
+        send_cell.send(CellChange{index: tape.pointer(), content: tape.current(), action: Action::Watch(label), span}).unwrap();
+    }
+}
+
+//the cells a clear or multiply idiom on the counter at index writes
+fn written_by(idiom: &Idiom, index: isize) -> Vec<isize> {
+    let mut written = vec![index];
+
+    if let Idiom::MultiplyAdd(targets) = idiom {
+        written.extend(targets.iter().map(|(distance, _)| index + distance));
+    }
+    written
+}
+
+fn reaches_targets(idiom: &Idiom, tape: &Tape) -> bool {
+    match idiom {
+        Idiom::MultiplyAdd(targets) => targets.iter().all(|(distance, _)| tape.reaches(*distance)),
+        _ => true
+    }
+}
+
+//applies a clear or multiply idiom to a cell that is not 0
+fn run_idiom(idiom: &Idiom, tape: &mut Tape) {
+    if let Idiom::MultiplyAdd(targets) = idiom {
+        let value = tape.current();
+        let modulus = 1 << tape.cell_type().bits();
+
+        for (distance, factor) in targets {
+            tape.move_by(*distance);
+            //the loop ran value times modulo the cell size, so the product is too
+            tape.add(factor.wrapping_mul(value).rem_euclid(modulus));
+            tape.move_by(-distance);
+        }
+    }
+    tape.set(0);
+}
+
+//describes what a clear or multiply idiom did with the counter cell
+fn describe_idiom(idiom: &Idiom, counter: isize) -> String {
+    let mut steps = Vec::new();
+
+    if let Idiom::MultiplyAdd(targets) = idiom {
+        for (distance, factor) in targets {
+            steps.push(match factor {
+                1 => format!("add cell {} to cell {}", counter, counter + distance),
+                -1 => format!("subtract cell {} from cell {}", counter, counter + distance),
+                _ => format!("add {} × cell {} to cell {}", factor, counter, counter + distance)
+            });
+        }
+    }
+
+    steps.push(format!("clear cell {}", counter));
+    steps.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CellType, Config, Eof, Overflow, TapeGrowth};
+    use crate::controller::Command;
+    use crate::bytecode::compile;
+    use crate::lexer::lex;
+    use crate::optimizer::{optimize, Optimizations};
+    use crate::parser::parse;
+
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn program(source: &str) -> Program {
+        compile(&optimize(&parse(lex(source)), &Optimizations::default()))
+    }
+
+    //most programs expect this, and the idioms only run on it
+    fn wrapping() -> Config {
+        Config{overflow: Overflow::Wrap, ..Config::default()}
+    }
+
+    #[test]
+    fn hello_world() {
+        let program = program("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.");
+        let mut tape = Tape::new(&Config::default());
+
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        run(&program, &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        let output: String = receive_cell.iter()
+            .filter(|change| change.action == Action::Output)
+            .map(|change| change.content as u8 as char)
+            .collect();
+
+        assert_eq!(output, "Hello World!\n");
+    }
+
+    #[test]
+    fn step_runs_one_instruction() {
+        let program = compile(&optimize(&parse(lex("+++")), &Optimizations::none()));
+        let controller = ExecutionController::new();
+        controller.pause();
+
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let handle = {
+            let controller = controller.clone();
+            thread::spawn(move || {
+                let mut tape = Tape::new(&Config::default());
+                run(&program, &mut tape, send_cell, &receive_data, &controller);
+                tape.current()
+            })
+        };
+
+        controller.step();
+        assert_eq!(receive_cell.recv().unwrap().content, 1);
+        assert!(receive_cell.recv_timeout(Duration::from_millis(50)).is_err());
+
+        controller.step();
+        assert_eq!(receive_cell.recv().unwrap().content, 2);
+
+        controller.cancel();
+        assert_eq!(handle.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn cancel_stops_infinite_loop() {
+        let program = program("+[]");
+        let controller = ExecutionController::new();
+
+        let (send_cell, _receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let handle = {
+            let controller = controller.clone();
+            thread::spawn(move || {
+                let mut tape = Tape::new(&Config::default());
+                run(&program, &mut tape, send_cell, &receive_data, &controller);
+            })
+        };
+
+        thread::sleep(Duration::from_millis(10));
+        controller.cancel();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn pointer_stops_at_configured_length() {
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let mut tape = Tape::new(&Config{tape_length: 4, ..Config::default()});
+        run(&program(">>>+>"), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        assert_eq!(tape.pointer(), 3);
+        assert_eq!(receive_cell.iter().last().unwrap().action, Action::Error(String::from("Data pointer out of bounds at index 4")));
+    }
+
+    #[test]
+    fn wrapping_clears_with_minus_loop() {
+        let (send_cell, _receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let mut tape = Tape::new(&wrapping());
+        run(&program("-->-[-]<"), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        assert_eq!(&tape.cells()[..2], &[254, 0]);
+    }
+
+    #[test]
+    fn sixteen_bit_cells() {
+        let (send_cell, _receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let config = Config{overflow: Overflow::Error, cell_type: CellType::U16, ..Config::default()};
+        let mut tape = Tape::new(&config);
+        run(&program("++++[>++++++++<-]>[<++++++++>-]<"), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        assert_eq!(tape.current(), 256);
+    }
+
+    //feeds input to a program and collects what it writes
+    fn run_with_input(source: &str, config: &Config, input: &[u8]) -> (Vec<u8>, Option<Action>) {
+        let program = program(source);
+        let mut tape = Tape::new(config);
+        let mut input = input.iter();
+
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (send_data, receive_data) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            run(&program, &mut tape, send_cell, &receive_data, &ExecutionController::new());
+        });
+
+        let mut output = Vec::new();
+        let mut error = None;
+        for change in receive_cell {
+            match change.action {
+                Action::Input => send_data.send(input.next().map(|&byte| byte as i64)).unwrap(),
+                Action::Output => output.push(change.content as u8),
+                Action::Error(_) => error = Some(change.action),
+                Action::Tape | Action::Idiom(_) | Action::Breakpoint | Action::Watch(_) | Action::Rewound(_) => ()
+            }
+        }
+        handle.join().unwrap();
+
+        (output, error)
+    }
+
+    #[test]
+    fn cat_until_eof() {
+        let zero = Config{eof: Eof::Zero, ..Config::default()};
+        assert_eq!(run_with_input(",[.,]", &zero, b"cat"), (b"cat".to_vec(), None));
+
+        let minus_one = Config{eof: Eof::MinusOne, ..wrapping()};
+        assert_eq!(run_with_input(",+[-.,+]", &minus_one, b"cat"), (b"cat".to_vec(), None));
+    }
+
+    //run or run_headless
+    type Runner = fn(&Program, &mut Tape, Sender<CellChange>, &Receiver<Option<i64>>, &ExecutionController);
+
+    //the input, output and error events of the runner for the program
+    fn events(runner: Runner, source: &str, config: &Config, input: &[u8]) -> Vec<CellChange> {
+        let program = program(source);
+        let mut tape = Tape::new(config);
+        let mut input = input.iter();
+
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (send_data, receive_data) = mpsc::channel();
+
+        let handle = thread::spawn(move || runner(&program, &mut tape, send_cell, &receive_data, &ExecutionController::new()));
+
+        let mut events = Vec::new();
+        for change in receive_cell {
+            match change.action {
+                Action::Input => send_data.send(input.next().map(|&byte| byte as i64)).unwrap(),
+                Action::Output | Action::Error(_) => (),
+                _ => continue
+            }
+            events.push(change);
+        }
+        handle.join().unwrap();
+        events
+    }
+
+    #[test]
+    fn headless_runs_like_run() {
+        let programs = [
+            ("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.", wrapping(), &b""[..]),
+            (",[.,]", Config{eof: Eof::Zero, ..Config::default()}, b"cat"),
+            (",.,.,", Config{eof: Eof::Error, ..Config::default()}, b"a"),
+            ("+[->+<]>[-]>-", Config::default(), b""),
+            ("++[->+++<]>.+[>]", Config{tape_length: 5, ..wrapping()}, b""),
+            ("+[<<+>>-]<<.", Config{growth: TapeGrowth::Right, ..wrapping()}, b""),
+            ("-[-->+<]>.", Config{overflow: Overflow::Saturate, ..Config::default()}, b""),
+            ("+]", Config::default(), b""),
+        ];
+
+        for (source, config, input) in programs.iter() {
+            let headless = events(run_headless, source, config, input);
+            assert!(!headless.is_empty(), "{}", source);
+            assert_eq!(headless, events(run, source, config, input), "{}", source);
+        }
+    }
+
+    #[test]
+    fn eof_policies() {
+        let source = "+++++,.";
+
+        assert_eq!(run_with_input(source, &Config{eof: Eof::Unchanged, ..Config::default()}, b""), (vec![5], None));
+        assert_eq!(run_with_input(source, &Config{eof: Eof::Zero, ..Config::default()}, b""), (vec![0], None));
+        assert_eq!(run_with_input(source, &Config{eof: Eof::MinusOne, ..Config::default()}, b""), (vec![255], None));
+        assert_eq!(run_with_input(source, &Config{eof: Eof::Error, ..Config::default()}, b""), (vec![], Some(Action::Error(String::from("End of input at index 5")))));
+    }
+
+    #[test]
+    fn folded_errors_point_at_the_failing_character() {
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let mut tape = Tape::new(&Config{overflow: Overflow::Error, ..Config::default()});
+        run(&program(&"+".repeat(300)), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        assert_eq!(tape.current(), 255);
+        assert_eq!(receive_cell.iter().last().unwrap().action, Action::Error(String::from("Addition overflow at index 255")));
+
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let mut tape = Tape::new(&Config{tape_length: 3, ..Config::default()});
+        run(&program("+ >> >>"), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        assert_eq!(tape.pointer(), 2);
+        assert_eq!(receive_cell.iter().last().unwrap().action, Action::Error(String::from("Data pointer out of bounds at index 5")));
+    }
+
+    #[test]
+    fn folded_runs_are_one_event() {
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let mut tape = Tape::new(&Config::default());
+        run(&program("+++ >>"), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        let spans: Vec<Span> = receive_cell.iter().map(|change| change.span).collect();
+        assert_eq!(spans, vec![Span::new(0, 3), Span::new(4, 6)]);
+    }
+
+    //runs the source once as written and once optimized
+    fn both_ways(source: &str, config: &Config) -> (Tape, Tape) {
+        let run_with = |optimizations: &Optimizations| {
+            let (send_cell, _receive_cell) = mpsc::channel();
+            let (_send_data, receive_data) = mpsc::channel();
+
+            let mut tape = Tape::new(config);
+            run(&compile(&optimize(&parse(lex(source)), optimizations)), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+            tape
+        };
+
+        (run_with(&Optimizations::none()), run_with(&Optimizations::default()))
+    }
+
+    #[test]
+    fn idioms_match_their_loops() {
+        let programs = [
+            "+++++[-]>-[+]",
+            "+++++[->+<]>>-[->++>+++<<]",
+            ">>+++[-<<-->>]",
+            "+>+>+>>+<[<]>[>]",
+            "-[->+<]",
+        ];
+
+        for cell_type in [CellType::U8, CellType::I8, CellType::U16] {
+            for source in programs {
+                let (plain, optimized) = both_ways(source, &Config{tape_length: 16, cell_type, ..Config::default()});
+                assert_eq!(optimized, plain, "{} with {:?} cells", source, cell_type);
+            }
+        }
+    }
+
+    #[test]
+    fn idioms_without_wrapping_run_their_body() {
+        let config = Config{overflow: Overflow::Error, ..Config::default()};
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let mut tape = Tape::new(&config);
+        run(&program("+[+]"), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        assert_eq!(tape.current(), 255);
+        assert_eq!(receive_cell.iter().last().unwrap().action, Action::Error(String::from("Addition overflow at index 2")));
+
+        let (plain, optimized) = both_ways("+++[->+<]", &Config{tape_length: 1, ..Config::default()});
+        assert_eq!(optimized, plain);
+    }
+
+    #[test]
+    fn idiom_labels() {
+        //only a controller that asks for them gets the labels
+        let labelled = |labels: bool| -> Vec<String> {
+            let (send_cell, receive_cell) = mpsc::channel();
+            let (_send_data, receive_data) = mpsc::channel();
+            let controller = ExecutionController::new();
+            controller.set_idiom_labels(labels);
+
+            let mut tape = Tape::new(&wrapping());
+            run(&program(">++[->+++<]>[-]+[<]"), &mut tape, send_cell, &receive_data, &controller);
+
+            receive_cell.iter()
+                .filter_map(|change| match change.action { Action::Idiom(label) => Some(label), _ => None })
+                .collect()
+        };
+
+        assert_eq!(labelled(true), vec!["add 3 × cell 1 to cell 2, clear cell 1", "clear cell 2", "scan left from cell 2 to cell 1"]);
+        assert!(labelled(false).is_empty());
+    }
+
+    #[test]
+    fn spans_skip_comments() {
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let mut tape = Tape::new(&wrapping());
+        run(&program("x+ y+\n[-]z."), &mut tape, send_cell, &receive_data, &ExecutionController::new());
+
+        let spans: Vec<Span> = receive_cell.iter().map(|change| change.span).collect();
+        assert_eq!(spans, vec![Span::new(1, 5), Span::new(6, 9), Span::new(10, 11)]);
+    }
+
+    //starts the program on a thread, controlled by controller
+    fn start(source: &str, optimizations: &Optimizations, controller: &ExecutionController) -> (mpsc::Receiver<CellChange>, thread::JoinHandle<i64>) {
+        let program = compile(&optimize(&parse(lex(source)), optimizations));
+
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (_send_data, receive_data) = mpsc::channel();
+
+        let handle = {
+            let controller = controller.clone();
+            thread::spawn(move || {
+                let mut tape = Tape::new(&wrapping());
+                run(&program, &mut tape, send_cell, &receive_data, &controller);
+                tape.current()
+            })
+        };
+
+        (receive_cell, handle)
+    }
+
+    //starts the program unoptimized, with breakpoints at offsets
+    fn run_with_breakpoints(source: &str, offsets: &[usize]) -> (ExecutionController, mpsc::Receiver<CellChange>, thread::JoinHandle<i64>) {
+        let controller = ExecutionController::new();
+        controller.set_breakpoints(offsets.iter().copied());
+
+        let (receive_cell, handle) = start(source, &Optimizations::none(), &controller);
+        (controller, receive_cell, handle)
+    }
+
+    #[test]
+    fn breakpoints_pause_before_their_instruction() {
+        let (controller, receive_cell, handle) = run_with_breakpoints("++[-]", &[1, 4]);
+
+        assert_eq!(receive_cell.recv().unwrap().span, Span::at(0));
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 0, content: 1, action: Action::Breakpoint, span: Span::at(1)});
+        assert!(receive_cell.recv_timeout(Duration::from_millis(50)).is_err());
+        assert!(controller.is_paused());
+
+        controller.step();
+        assert_eq!(receive_cell.recv().unwrap().content, 2);
+
+        //the ] stops on every round of the loop
+        controller.resume();
+        assert_eq!(receive_cell.recv().unwrap().content, 1);
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 0, content: 1, action: Action::Breakpoint, span: Span::at(4)});
+
+        controller.set_breakpoints(Vec::new());
+        controller.resume();
+        assert_eq!(handle.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn hash_needs_a_breakpoint() {
+        let (_controller, _receive_cell, handle) = run_with_breakpoints("+#+", &[]);
+        assert_eq!(handle.join().unwrap(), 2);
+
+        let (controller, receive_cell, handle) = run_with_breakpoints("+#+", &[1]);
+        receive_cell.recv().unwrap();
+        assert_eq!(receive_cell.recv().unwrap().action, Action::Breakpoint);
+
+        controller.step();
+        assert_eq!(receive_cell.recv().unwrap().content, 2);
+        controller.cancel();
+        assert_eq!(handle.join().unwrap(), 2);
+    }
+
+    //the labels of the watches that trigger, resuming after each one
+    fn triggered(source: &str, optimizations: &Optimizations, watches: &[&str]) -> Vec<(String, Span)> {
+        let controller = ExecutionController::new();
+        controller.set_watches(watches.iter().map(|watch| watch.parse().unwrap()));
+
+        let (receive_cell, handle) = start(source, optimizations, &controller);
+        let mut labels = Vec::new();
+
+        for change in receive_cell {
+            if let Action::Watch(label) = change.action {
+                assert!(controller.is_paused());
+                labels.push((label, change.span));
+                controller.resume();
+            }
+        }
+        handle.join().unwrap();
+
+        labels
+    }
+
+    #[test]
+    fn conditions_stop_when_they_become_true() {
+        let labels = triggered("+>++<->+", &Optimizations::none(), &["cell[1] == 2", "ptr > 0", "cell == 0"]);
+
+        assert_eq!(labels, vec![
+            (String::from("ptr > 0, cell == 0"), Span::at(1)),
+            (String::from("cell[1] == 2"), Span::at(3)),
+            (String::from("cell == 0"), Span::at(5)),
+            (String::from("ptr > 0"), Span::at(6)),
+        ]);
+    }
+
+    #[test]
+    fn cell_watches() {
+        let labels = triggered("+>+<[->+<]>[-]+-+", &Optimizations::default(), &["write cell[1]", "change cell[0]"]);
+
+        assert_eq!(labels, vec![
+            (String::from("cell 0 changed to 1"), Span::at(0)),
+            (String::from("cell 1 written"), Span::at(2)),
+            (String::from("cell 1 written, cell 0 changed to 0"), Span::new(4, 10)),
+            (String::from("cell 1 written"), Span::new(11, 14)),
+            (String::from("cell 1 written"), Span::at(14)),
+            (String::from("cell 1 written"), Span::at(15)),
+            (String::from("cell 1 written"), Span::at(16)),
+        ]);
+    }
+
+    #[test]
+    fn step_back_restores_the_tape() {
+        let controller = ExecutionController::new();
+        controller.set_history_limit(100);
+        controller.pause();
+        let (receive_cell, handle) = start("+>++.<", &Optimizations::none(), &controller);
+
+        for _ in 0..5 {
+            controller.step();
+            receive_cell.recv().unwrap();
+        }
+
+        //back to before the first + on cell 1, which was written twice and
+        //is under the pointer again
+        controller.step_back(3);
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 1, content: 0, action: Action::Rewound(0), span: Span::at(2)});
+        assert!(receive_cell.recv_timeout(Duration::from_millis(50)).is_err());
+        assert_eq!(controller.history_len(), 2);
+
+        //back to the start, cell 1 is no longer under the pointer
+        controller.step_back(10);
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 0, content: 0, action: Action::Rewound(0), span: Span::at(0)});
+        assert_eq!(controller.history_len(), 0);
+
+        controller.resume();
+        let outputs: Vec<i64> = receive_cell.iter().filter(|change| change.action == Action::Output).map(|change| change.content).collect();
+        assert_eq!(outputs, vec![2]);
+        assert_eq!(handle.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn reads_get_their_input_again() {
+        let program = compile(&optimize(&parse(lex(",.>")), &Optimizations::none()));
+        let controller = ExecutionController::new();
+        controller.set_history_limit(10);
+        controller.pause();
+
+        let (send_cell, receive_cell) = mpsc::channel();
+        let (send_data, receive_data) = mpsc::channel();
+
+        let handle = {
+            let controller = controller.clone();
+            thread::spawn(move || run(&program, &mut Tape::new(&Config::default()), send_cell, &receive_data, &controller))
+        };
+
+        controller.step();
+        assert_eq!(receive_cell.recv().unwrap().action, Action::Input);
+        send_data.send(Some(7)).unwrap();
+        controller.step();
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 0, content: 7, action: Action::Output, span: Span::at(1)});
+
+        controller.step_back(2);
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 0, content: 0, action: Action::Rewound(0), span: Span::at(0)});
+
+        //the , takes the 7 again instead of asking for input
+        controller.resume();
+        let actions: Vec<(Action, i64)> = receive_cell.iter().map(|change| (change.action, change.content)).collect();
+        assert_eq!(actions, vec![(Action::Tape, 7), (Action::Output, 7), (Action::Tape, 0)]);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn step_back_over_skipped_idioms() {
+        //the targets of the multiply loops are off the tape, or not reached
+        //yet, while their counter is 0
+        let growing = Config{tape_length: 1, growth: TapeGrowth::Right, ..wrapping()};
+        for (source, config) in [("[-<+>]+>+", wrapping()), ("[-<+>]+>+", Config::default()), ("[->+<]+>+", growing)] {
+            let program = program(source);
+            let controller = ExecutionController::new();
+            controller.set_history_limit(10);
+            controller.pause();
+
+            let (send_cell, receive_cell) = mpsc::channel();
+            let (_send_data, receive_data) = mpsc::channel();
+
+            let handle = {
+                let controller = controller.clone();
+                thread::spawn(move || {
+                    let mut tape = Tape::new(&config);
+                    run(&program, &mut tape, send_cell, &receive_data, &controller);
+                    tape.current()
+                })
+            };
+
+            //the skipped loop reports nothing, the first + does
+            controller.step_by(2);
+            assert_eq!(receive_cell.recv().unwrap().content, 1);
+
+            controller.step_back(2);
+            assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 0, content: 0, action: Action::Rewound(0), span: Span::new(0, 6)});
+
+            controller.resume();
+            assert_eq!(handle.join().unwrap(), 1, "{}", source);
+        }
+    }
+
+    #[test]
+    fn step_back_over_an_idiom() {
+        let controller = ExecutionController::new();
+        controller.set_history_limit(10);
+        controller.set_idiom_labels(true);
+        controller.pause();
+        let (receive_cell, handle) = start("++[->+++<]>", &Optimizations::default(), &controller);
+
+        controller.step_by(2);
+        let actions: Vec<Action> = receive_cell.iter().take(3).map(|change| change.action).collect();
+        assert_eq!(actions, vec![Action::Tape, Action::Tape, Action::Idiom(String::from("add 3 × cell 0 to cell 1, clear cell 0"))]);
+
+        //both cells go back, the counter is under the pointer
+        controller.step_back(1);
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 1, content: 0, action: Action::Tape, span: Span::new(2, 10)});
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 0, content: 2, action: Action::Rewound(0), span: Span::new(2, 10)});
+
+        controller.resume();
+        assert_eq!(handle.join().unwrap(), 6);
+    }
+
+    //pauses the program before the instruction at source offset pause_at,
+    //gives it the command and tells where it pauses next
+    fn pauses_after(source: &str, pause_at: usize, command: fn(&ExecutionController)) -> (Span, i64) {
+        let (controller, receive_cell, handle) = run_with_breakpoints(source, &[pause_at]);
+        while receive_cell.recv().unwrap().action != Action::Breakpoint {}
+
+        controller.set_breakpoints(Vec::new());
+        command(&controller);
+        while receive_cell.recv_timeout(Duration::from_millis(50)).is_ok() {}
+        assert!(controller.is_paused());
+
+        //the next instruction tells where the program is
+        controller.step();
+        let change = receive_cell.recv().unwrap();
+        controller.cancel();
+        handle.join().unwrap();
+
+        (change.span, change.content)
+    }
+
+    #[test]
+    fn step_over_runs_whole_loops() {
+        assert_eq!(pauses_after("++[->+<]>", 1, ExecutionController::step_over), (Span::at(8), 2));
+        assert_eq!(pauses_after("++[->+<]>", 3, ExecutionController::step_over), (Span::at(4), 0));
+        assert_eq!(pauses_after("+[->+[-]<]+", 4, ExecutionController::step_over), (Span::at(8), 0));
+    }
+
+    #[test]
+    fn finish_iteration_stops_at_the_next_one() {
+        assert_eq!(pauses_after("++[->+<]>", 4, ExecutionController::finish_iteration), (Span::at(3), 0));
+        assert_eq!(pauses_after("+[->+<]>", 4, ExecutionController::finish_iteration), (Span::at(7), 1));
+    }
+
+    #[test]
+    fn step_out_leaves_the_loop() {
+        assert_eq!(pauses_after("++[->+<]>", 4, ExecutionController::step_out), (Span::at(8), 2));
+        assert_eq!(pauses_after("+[>+[-]<-]+", 5, ExecutionController::step_out), (Span::at(7), 1));
+    }
+
+    #[test]
+    fn step_commands_run_exactly_that_many_instructions() {
+        let controller = ExecutionController::new();
+        controller.send(Command::Pause);
+        let (receive_cell, handle) = start("+++++", &Optimizations::none(), &controller);
+
+        controller.send(Command::Step(3));
+        let contents: Vec<i64> = receive_cell.iter().take(3).map(|change| change.content).collect();
+        assert_eq!(contents, vec![1, 2, 3]);
+        assert!(receive_cell.recv_timeout(Duration::from_millis(50)).is_err());
+
+        controller.send(Command::Cancel);
+        assert_eq!(handle.join().unwrap(), 3);
+    }
+
+    #[test]
+    fn breakpoints_end_a_step_command() {
+        let controller = ExecutionController::new();
+        controller.set_breakpoints(vec![2]);
+        controller.send(Command::Pause);
+        let (receive_cell, handle) = start("+++++", &Optimizations::none(), &controller);
+
+        controller.send(Command::Step(5));
+        let contents: Vec<i64> = receive_cell.iter().take(2).map(|change| change.content).collect();
+        assert_eq!(contents, vec![1, 2]);
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 0, content: 2, action: Action::Breakpoint, span: Span::at(2)});
+        assert!(receive_cell.recv_timeout(Duration::from_millis(50)).is_err());
+        assert!(controller.is_paused());
+        assert_eq!(controller.steps_left(), 0);
+
+        //the next step is one instruction, not what was left of the five
+        controller.send(Command::Step(1));
+        assert_eq!(receive_cell.recv().unwrap().content, 3);
+        assert!(receive_cell.recv_timeout(Duration::from_millis(50)).is_err());
+
+        controller.send(Command::Cancel);
+        assert_eq!(handle.join().unwrap(), 3);
+    }
+
+    #[test]
+    fn run_to_pauses_once() {
+        let controller = ExecutionController::new();
+        controller.send(Command::Pause);
+        let (receive_cell, handle) = start("++[>+<-]", &Optimizations::none(), &controller);
+
+        controller.send(Command::Step(2));
+        for _ in 0..2 {
+            receive_cell.recv().unwrap();
+        }
+        controller.send(Command::RunTo(4));
+        assert_eq!(receive_cell.recv().unwrap().span, Span::at(3));
+        assert_eq!(receive_cell.recv().unwrap(), CellChange{index: 1, content: 0, action: Action::Breakpoint, span: Span::at(4)});
+        assert!(controller.is_paused());
+
+        //the loop comes back to offset 3 after a resume, but the run to is over
+        controller.send(Command::Resume);
+        assert!(receive_cell.iter().all(|change| change.action != Action::Breakpoint));
+        assert_eq!(handle.join().unwrap(), 0);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::tape::Tape;
+
+use std::collections::VecDeque;
+
+//what an executed instruction changed, enough to undo it; going forward
+//again runs the instruction again
+#[derive(Debug)]
+pub(crate) struct Entry {
+    //position of the instruction in the program
+    pub(crate) position: usize,
+    //data pointer before the instruction
+    pub(crate) pointer: isize,
+    //cells the instruction may write, with their values before it
+    pub(crate) cells: Vec<(isize, i64)>,
+    //how many values had been written to the output before it
+    pub(crate) outputs: usize,
+    //the value a , read, None inside for the end of input
+    pub(crate) input: Option<Option<i64>>,
+}
+
+//the undo log of a run, together with the input that undoing reads gave
+//back, which reads running again take before asking for more
+#[derive(Debug)]
+#[derive(Default)]
+pub(crate) struct History {
+    entries: VecDeque<Entry>,
+    replay: Vec<Option<i64>>,
+}
+
+impl History {
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    //keeps at most limit entries, the oldest ones are forgotten first
+    pub(crate) fn record(&mut self, entry: Entry, limit: usize) {
+        if self.entries.len() >= limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    //puts the tape back as it was before the last recorded instruction
+    pub(crate) fn undo(&mut self, tape: &mut Tape) -> Option<Entry> {
+        let entry = self.entries.pop_back()?;
+
+        for &(index, value) in &entry.cells {
+            tape.restore(index, value);
+        }
+        tape.move_to(entry.pointer);
+
+        if let Some(input) = entry.input {
+            self.replay.push(input);
+        }
+        Some(entry)
+    }
+
+    //input given back by undoing a read, the last undone read first
+    pub(crate) fn replayed_input(&mut self) -> Option<Option<i64>> {
+        self.replay.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn forgets_the_oldest_entries() {
+        let mut history = History::default();
+        for position in 0..5 {
+            history.record(Entry{position, pointer: 0, cells: Vec::new(), outputs: 0, input: None}, 3);
+        }
+        let mut tape = Tape::new(&Config::default());
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.undo(&mut tape).unwrap().position, 4);
+        assert_eq!(history.undo(&mut tape).unwrap().position, 3);
+        assert_eq!(history.undo(&mut tape).unwrap().position, 2);
+        assert!(history.undo(&mut tape).is_none());
+    }
+}
@@ -0,0 +1,608 @@
+use crate::config::{CellType, Config, Eof, Overflow, TapeGrowth};
+use crate::optimizer::{Idiom, Node, Op};
+use crate::span::Span;
+
+use std::any::Any;
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+/// Why a compiled program stopped before its end, with the same message and
+/// span `run` gives its `Action::Error`.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct JitError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// A program compiled to x86-64 machine code, which runs it much faster
+/// than `run` but without any events, so it cannot be paused, stepped or
+/// watched. Input and output go through callbacks.
+#[derive(Debug)]
+pub struct Jit {
+    code: ExecutableBuffer,
+    //what the error callbacks need to know about the instruction that failed
+    sites: Vec<Site>,
+    cell_type: CellType,
+    eof: Eof,
+    tape_length: usize,
+}
+
+//an instruction that can stop the program
+#[derive(Debug)]
+enum Site {
+    //a move by the amount, with the characters it was made from
+    Move(isize, Vec<usize>),
+    //a read at the offset
+    Read(usize),
+}
+
+//what the callbacks work with while the machine code runs
+struct Context<'a> {
+    jit: &'a Jit,
+    input: &'a mut dyn FnMut() -> Option<u8>,
+    output: &'a mut dyn FnMut(u8) -> bool,
+    tape: *mut u8,
+    error: Option<JitError>,
+    //a panic of a callback, which must not unwind through the machine code
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl Context<'_> {
+    //calls a callback, a panic stops the program and is kept to be resumed
+    //once the machine code returned
+    fn call<T>(&mut self, callback: impl FnOnce(&mut Self) -> T) -> Option<T> {
+        match panic::catch_unwind(AssertUnwindSafe(|| callback(self))) {
+            Ok(value) => Some(value),
+            Err(payload) => {
+                self.panic = Some(payload);
+                None
+            }
+        }
+    }
+}
+
+//the compiled program takes the tape, the end of the tape and the context,
+//and returns 0 when it ran to its end
+type Entry = unsafe extern "sysv64" fn(*mut u8, *mut u8, *mut Context) -> u64;
+
+//status of a program that stopped with an error in the context
+const STOPPED: u64 = 1;
+
+impl Jit {
+    /// Compiles the optimized program for a tape set up like `config`. Only
+    /// fixed tapes of wrapping cells are supported, for anything else, and
+    /// for programs with unmatched brackets, this gives None and the program
+    /// should be run by `run`.
+    pub fn compile(nodes: &[Node], config: &Config) -> Option<Jit> {
+        if config.growth != TapeGrowth::Fixed || config.overflow != Overflow::Wrap {
+            return None;
+        }
+
+        let mut assembler = Assembler::new(config.cell_type);
+        assembler.prologue();
+        assembler.block(nodes)?;
+        assembler.epilogue();
+
+        Some(Jit {
+            code: ExecutableBuffer::new(&assembler.code)?,
+            sites: assembler.sites,
+            cell_type: config.cell_type,
+            eof: config.eof,
+            tape_length: config.tape_length.max(1),
+        })
+    }
+
+    /// Runs the program on an empty tape. `input` is called for every `,`
+    /// and gives the next byte, or None at the end of the input, and
+    /// `output` gets every byte written by `.` and gives false to stop the
+    /// program there, as if it had ended. If a callback panics, the program
+    /// stops and the panic carries on from here.
+    pub fn run(&self, input: &mut dyn FnMut() -> Option<u8>, output: &mut dyn FnMut(u8) -> bool) -> Result<(), JitError> {
+        let width = cell_width(self.cell_type);
+        let mut tape = vec![0u8; self.tape_length * width];
+        let range = tape.as_mut_ptr_range();
+
+        let mut context = Context{jit: self, input, output, tape: range.start, error: None, panic: None};
+
+        //the code only touches the tape between the two pointers, and only
+        //calls back with the context it was given
+        let status = unsafe {
+            let entry: Entry = std::mem::transmute(self.code.pointer);
+            entry(range.start, range.end, &mut context)
+        };
+
+        if let Some(payload) = context.panic {
+            panic::resume_unwind(payload);
+        }
+
+        match context.error {
+            Some(error) if status == STOPPED => Err(error),
+            _ => Ok(())
+        }
+    }
+}
+
+fn cell_width(cell_type: CellType) -> usize {
+    cell_type.bits() as usize / 8
+}
+
+extern "sysv64" fn write_byte(context: *mut Context, byte: u64) -> u64 {
+    let context = unsafe { &mut *context };
+
+    match context.call(|context| (context.output)(byte as u8)) {
+        Some(true) => 0,
+        _ => STOPPED
+    }
+}
+
+//reads into the cell at cell, following the end of input policy
+extern "sysv64" fn read_byte(context: *mut Context, cell: *mut u8, site: u64) -> u64 {
+    let context = unsafe { &mut *context };
+    let cell_type = context.jit.cell_type;
+
+    let input = match context.call(|context| (context.input)()) {
+        Some(input) => input,
+        None => return STOPPED
+    };
+
+    let value = match input {
+        Some(byte) => byte as i64,
+        None => match context.jit.eof {
+            Eof::Error => {
+                let offset = match context.jit.sites[site as usize] {
+                    Site::Read(offset) => offset,
+                    Site::Move(..) => unreachable!()
+                };
+                context.error = Some(JitError{message: format!("End of input at index {}", offset), span: Span::at(offset)});
+                return STOPPED;
+            },
+            Eof::Unchanged => return 0,
+            Eof::Zero => 0,
+            Eof::MinusOne => -1,
+        }
+    };
+
+    //cells are little endian and hold the value as the bits of the cell type
+    let bytes = (cell_type.wrap(value) as u32).to_le_bytes();
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), cell, cell_width(cell_type)) };
+    0
+}
+
+//a move left the tape, cell is where it would have ended up
+extern "sysv64" fn out_of_bounds(context: *mut Context, site: u64, cell: *mut u8) -> u64 {
+    let context = unsafe { &mut *context };
+    let (amount, offsets) = match &context.jit.sites[site as usize] {
+        Site::Move(amount, offsets) => (*amount, offsets),
+        Site::Read(_) => unreachable!()
+    };
+
+    let width = cell_width(context.jit.cell_type) as isize;
+    let before = (cell as isize - context.tape as isize) / width - amount;
+    //the character that took the pointer off the tape, like move_pointer of run
+    let steps = if amount > 0 { context.jit.tape_length as isize - 1 - before } else { before };
+    let offset = offsets[steps as usize];
+
+    context.error = Some(JitError{message: format!("Data pointer out of bounds at index {}", offset), span: Span::at(offset)});
+    STOPPED
+}
+
+//rbx points at the current cell, r12 at the start of the tape, r13 just past
+//its end and r14 at the context
+struct Assembler {
+    code: Vec<u8>,
+    width: usize,
+    sites: Vec<Site>,
+    //the rel32 of jumps to the error handler of the site
+    stubs: Vec<(usize, u32)>,
+    //the rel32 of jumps to the exit with the status in eax
+    exits: Vec<usize>,
+}
+
+impl Assembler {
+    fn new(cell_type: CellType) -> Assembler {
+        Assembler{code: Vec::new(), width: cell_width(cell_type), sites: Vec::new(), stubs: Vec::new(), exits: Vec::new()}
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    //emits a jump with the opcode and gives the position of its rel32
+    fn jump(&mut self, opcode: &[u8]) -> usize {
+        self.emit(opcode);
+        self.emit_u32(0);
+        self.code.len() - 4
+    }
+
+    //makes the jump with its rel32 at at go to target
+    fn patch(&mut self, at: usize, target: usize) {
+        let relative = target as i64 - (at as i64 + 4);
+        self.code[at..at + 4].copy_from_slice(&(relative as i32).to_le_bytes());
+    }
+
+    //an opcode and its immediate for the width of a cell
+    fn sized(&mut self, byte: &[u8], word: &[u8], dword: &[u8], value: u32) {
+        match self.width {
+            1 => {
+                self.emit(byte);
+                self.emit(&[value as u8]);
+            },
+            2 => {
+                self.emit(&[0x66]);
+                self.emit(word);
+                self.emit(&(value as u16).to_le_bytes());
+            },
+            _ => {
+                self.emit(dword);
+                self.emit_u32(value);
+            }
+        }
+    }
+
+    fn call(&mut self, function: usize) {
+        //mov rax, function; call rax
+        self.emit(&[0x48, 0xB8]);
+        self.emit(&(function as u64).to_le_bytes());
+        self.emit(&[0xFF, 0xD0]);
+    }
+
+    //goes to the exit when the callback gave a status other than 0
+    fn exit_unless_zero(&mut self) {
+        //test eax, eax; jnz exit
+        self.emit(&[0x85, 0xC0]);
+        let at = self.jump(&[0x0F, 0x85]);
+        self.exits.push(at);
+    }
+
+    fn prologue(&mut self) {
+        //push rbx, r12, r13, r14 and r15, which also aligns the stack for calls
+        self.emit(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+        //mov rbx, rdi; mov r12, rdi; mov r13, rsi; mov r14, rdx
+        self.emit(&[0x48, 0x89, 0xFB, 0x49, 0x89, 0xFC, 0x49, 0x89, 0xF5, 0x49, 0x89, 0xD6]);
+    }
+
+    fn epilogue(&mut self) {
+        //xor eax, eax
+        self.emit(&[0x31, 0xC0]);
+        let exit = self.code.len();
+        //pop r15, r14, r13, r12 and rbx; ret
+        self.emit(&[0x41, 0x5F, 0x41, 0x5E, 0x41, 0x5D, 0x41, 0x5C, 0x5B, 0xC3]);
+
+        //the site is in esi and the cell the move would have ended up on in rbx
+        let error = self.code.len();
+        //mov rdi, r14; mov rdx, rbx
+        self.emit(&[0x4C, 0x89, 0xF7, 0x48, 0x89, 0xDA]);
+        self.call(out_of_bounds as *const () as usize);
+        let at = self.jump(&[0xE9]);
+        self.patch(at, exit);
+
+        for (at, site) in std::mem::take(&mut self.stubs) {
+            let stub = self.code.len();
+            self.patch(at, stub);
+            //mov esi, site; jmp error
+            self.emit(&[0xBE]);
+            self.emit_u32(site);
+            let at = self.jump(&[0xE9]);
+            self.patch(at, error);
+        }
+        for at in std::mem::take(&mut self.exits) {
+            self.patch(at, exit);
+        }
+    }
+
+    fn site(&mut self, site: Site) -> u32 {
+        self.sites.push(site);
+        (self.sites.len() - 1) as u32
+    }
+
+    //None if the program has an error, which only run reports properly
+    fn block(&mut self, nodes: &[Node]) -> Option<()> {
+        for node in nodes {
+            match &node.op {
+                Op::Add(amount) => {
+                    let value = amount.rem_euclid(1 << (self.width * 8)) as u32;
+                    //add [rbx], value
+                    self.sized(&[0x80, 0x03], &[0x81, 0x03], &[0x81, 0x03], value);
+                },
+                Op::Move(amount) => self.move_pointer(*amount, &node.offsets)?,
+                Op::Write => {
+                    //mov rdi, r14; movzx esi, byte [rbx]
+                    self.emit(&[0x4C, 0x89, 0xF7, 0x0F, 0xB6, 0x33]);
+                    self.call(write_byte as *const () as usize);
+                    self.exit_unless_zero();
+                },
+                Op::Read => {
+                    let site = self.site(Site::Read(node.offsets[0]));
+                    //mov rdi, r14; mov rsi, rbx; mov edx, site
+                    self.emit(&[0x4C, 0x89, 0xF7, 0x48, 0x89, 0xDE, 0xBA]);
+                    self.emit_u32(site);
+                    self.call(read_byte as *const () as usize);
+                    self.exit_unless_zero();
+                },
+                Op::Loop(body) => self.loop_block(body)?,
+                Op::Idiom(Idiom::SetZero, _) => self.set_zero(),
+                Op::Idiom(Idiom::MultiplyAdd(targets), body) => self.multiply_add(targets, body)?,
+                Op::Idiom(Idiom::Scan(_), body) => self.loop_block(body)?,
+                Op::Breakpoint => (),
+                Op::Error(_) => return None,
+            }
+        }
+        Some(())
+    }
+
+    fn move_pointer(&mut self, amount: isize, offsets: &[usize]) -> Option<()> {
+        let bytes = i32::try_from(amount.checked_mul(self.width as isize)?).ok()?;
+        let site = self.site(Site::Move(amount, offsets.to_vec()));
+
+        //add rbx, bytes
+        self.emit(&[0x48, 0x81, 0xC3]);
+        self.emit_u32(bytes as u32);
+
+        let at = if amount > 0 {
+            //cmp rbx, r13; jae error
+            self.emit(&[0x4C, 0x39, 0xEB]);
+            self.jump(&[0x0F, 0x83])
+        }
+        else {
+            //cmp rbx, r12; jb error
+            self.emit(&[0x4C, 0x39, 0xE3]);
+            self.jump(&[0x0F, 0x82])
+        };
+        self.stubs.push((at, site));
+        Some(())
+    }
+
+    fn set_zero(&mut self) {
+        //mov [rbx], 0
+        self.sized(&[0xC6, 0x03], &[0xC7, 0x03], &[0xC7, 0x03], 0);
+    }
+
+    fn compare_zero(&mut self) {
+        //cmp [rbx], 0, with a sign extended byte for the wider cells
+        match self.width {
+            1 => self.emit(&[0x80, 0x3B, 0x00]),
+            2 => self.emit(&[0x66, 0x83, 0x3B, 0x00]),
+            _ => self.emit(&[0x83, 0x3B, 0x00]),
+        }
+    }
+
+    fn loop_block(&mut self, body: &[Node]) -> Option<()> {
+        //je after
+        self.compare_zero();
+        let skip = self.jump(&[0x0F, 0x84]);
+        let start = self.code.len();
+
+        self.block(body)?;
+
+        self.compare_zero();
+        //jne start
+        let back = self.jump(&[0x0F, 0x85]);
+        self.patch(back, start);
+        let after = self.code.len();
+        self.patch(skip, after);
+        Some(())
+    }
+
+    //adds in one go when every target is on the tape, like run, and runs the
+    //loop as written otherwise
+    fn multiply_add(&mut self, targets: &[(isize, i64)], body: &[Node]) -> Option<()> {
+        let mut slow = Vec::new();
+        let mut displacements = Vec::new();
+
+        for (distance, _) in targets {
+            let displacement = i32::try_from(distance.checked_mul(self.width as isize)?).ok()?;
+            displacements.push(displacement);
+
+            //lea rax, [rbx + displacement]; cmp rax, r12; jb slow; cmp rax, r13; jae slow
+            self.emit(&[0x48, 0x8D, 0x83]);
+            self.emit_u32(displacement as u32);
+            self.emit(&[0x4C, 0x39, 0xE0]);
+            slow.push(self.jump(&[0x0F, 0x82]));
+            self.emit(&[0x4C, 0x39, 0xE8]);
+            slow.push(self.jump(&[0x0F, 0x83]));
+        }
+
+        //the count into eax: movzx eax, byte [rbx], movzx eax, word [rbx] or mov eax, [rbx]
+        match self.width {
+            1 => self.emit(&[0x0F, 0xB6, 0x03]),
+            2 => self.emit(&[0x0F, 0xB7, 0x03]),
+            _ => self.emit(&[0x8B, 0x03]),
+        }
+        for ((_, factor), displacement) in targets.iter().zip(displacements) {
+            //imul ecx, eax, factor, which is right modulo the cell size
+            self.emit(&[0x69, 0xC8]);
+            self.emit_u32(factor.rem_euclid(1 << 32) as u32);
+
+            //add [rbx + displacement], cl, cx or ecx
+            match self.width {
+                1 => self.emit(&[0x00, 0x8B]),
+                2 => self.emit(&[0x66, 0x01, 0x8B]),
+                _ => self.emit(&[0x01, 0x8B]),
+            }
+            self.emit_u32(displacement as u32);
+        }
+        self.set_zero();
+        let done = self.jump(&[0xE9]);
+
+        let loop_start = self.code.len();
+        for at in slow {
+            self.patch(at, loop_start);
+        }
+        self.loop_block(body)?;
+
+        let after = self.code.len();
+        self.patch(done, after);
+        Some(())
+    }
+}
+
+//memory for the machine code, writable while it is filled and executable
+//afterwards
+#[derive(Debug)]
+struct ExecutableBuffer {
+    pointer: *mut c_void,
+    length: usize,
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(address: *mut c_void, length: usize, protection: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn mprotect(address: *mut c_void, length: usize, protection: i32) -> i32;
+    fn munmap(address: *mut c_void, length: usize) -> i32;
+}
+
+impl ExecutableBuffer {
+    //None if the memory cannot be had
+    fn new(code: &[u8]) -> Option<ExecutableBuffer> {
+        let length = code.len().max(1);
+
+        unsafe {
+            let pointer = mmap(ptr::null_mut(), length, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+            //MAP_FAILED
+            if pointer as isize == -1 {
+                return None;
+            }
+            let buffer = ExecutableBuffer{pointer, length};
+
+            ptr::copy_nonoverlapping(code.as_ptr(), pointer as *mut u8, code.len());
+            if mprotect(pointer, length, PROT_READ | PROT_EXEC) != 0 {
+                return None;
+            }
+            Some(buffer)
+        }
+    }
+}
+
+impl Drop for ExecutableBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.pointer, self.length);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::optimizer::{optimize, Optimizations};
+    use crate::parser::parse;
+
+    //the only overflow policy the jit compiles
+    fn wrapping() -> Config {
+        Config{overflow: Overflow::Wrap, ..Config::default()}
+    }
+
+    //compiles and runs the program with the input, and gives its output
+    fn jit_run(source: &str, config: &Config, input: &[u8]) -> (Vec<u8>, Result<(), JitError>) {
+        let jit = Jit::compile(&optimize(&parse(lex(source)), &config.optimizations), config).unwrap();
+        let mut input = input.iter().copied();
+        let mut output = Vec::new();
+
+        let result = jit.run(&mut || input.next(), &mut |byte| { output.push(byte); true });
+        (output, result)
+    }
+
+    #[test]
+    fn hello_world() {
+        let source = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+        for optimizations in [Optimizations::default(), Optimizations::none()] {
+            let config = Config{optimizations, ..wrapping()};
+            assert_eq!(jit_run(source, &config, b""), (b"Hello World!\n".to_vec(), Ok(())));
+        }
+    }
+
+    #[test]
+    fn cell_types() {
+        //256 only fits cells wider than a byte
+        let source = "++++++++++++++++[>++++++++++++++++<-]>[>+<[-]]>.";
+
+        for (cell_type, output) in [(CellType::U8, 0), (CellType::I8, 0), (CellType::U16, 1), (CellType::I32, 1)] {
+            let config = Config{cell_type, ..wrapping()};
+            assert_eq!(jit_run(source, &config, b"").0, vec![output]);
+        }
+
+        let config = Config{cell_type: CellType::I16, ..wrapping()};
+        assert_eq!(jit_run("-[>+<-----]>.", &config, b"").0, vec![0x33]);
+    }
+
+    #[test]
+    fn end_of_input() {
+        let zero = Config{eof: Eof::Zero, ..wrapping()};
+        assert_eq!(jit_run(",[.,]", &zero, b"cat"), (b"cat".to_vec(), Ok(())));
+
+        let minus_one = Config{eof: Eof::MinusOne, ..wrapping()};
+        assert_eq!(jit_run(",+[-.,+]", &minus_one, b"dog"), (b"dog".to_vec(), Ok(())));
+
+        let error = Config{eof: Eof::Error, ..wrapping()};
+        assert_eq!(jit_run(",.,", &error, b"a"), (b"a".to_vec(), Err(JitError{message: String::from("End of input at index 2"), span: Span::at(2)})));
+    }
+
+    #[test]
+    fn pointer_errors_point_at_the_failing_character() {
+        let short = Config{tape_length: 3, ..wrapping()};
+
+        assert_eq!(jit_run(">> >", &short, b"").1, Err(JitError{message: String::from("Data pointer out of bounds at index 3"), span: Span::at(3)}));
+        assert_eq!(jit_run("><<", &short, b"").1, Err(JitError{message: String::from("Data pointer out of bounds at index 2"), span: Span::at(2)}));
+        //the multiply loop cannot add beyond the tape, so it runs as written
+        assert_eq!(jit_run("+[->>>+<<<]", &short, b"").1, Err(JitError{message: String::from("Data pointer out of bounds at index 5"), span: Span::at(5)}));
+        assert_eq!(jit_run("+[->>+<<]>>.", &short, b"").0, vec![1]);
+    }
+
+    #[test]
+    fn output_can_stop_the_program() {
+        let jit = Jit::compile(&optimize(&parse(lex("+[.]")), &Optimizations::default()), &wrapping()).unwrap();
+        let mut written = 0;
+
+        let result = jit.run(&mut || None, &mut |_| { written += 1; written < 3 });
+        assert_eq!((written, result), (3, Ok(())));
+    }
+
+    #[test]
+    fn callback_panics_reach_the_caller() {
+        let jit = Jit::compile(&optimize(&parse(lex("+[,.]")), &Optimizations::default()), &wrapping()).unwrap();
+
+        let input = panic::catch_unwind(|| jit.run(&mut || panic!("input"), &mut |_| true)).unwrap_err();
+        assert_eq!(input.downcast_ref::<&str>(), Some(&"input"));
+
+        let mut written = 0;
+        let output = panic::catch_unwind(AssertUnwindSafe(|| jit.run(&mut || Some(1), &mut |_| {
+            written += 1;
+            if written == 3 {
+                panic!("output");
+            }
+            true
+        }))).unwrap_err();
+        assert_eq!((written, output.downcast_ref::<&str>()), (3, Some(&"output")));
+    }
+
+    #[test]
+    fn only_fixed_wrapping_tapes() {
+        let nodes = optimize(&parse(lex("+.")), &Optimizations::default());
+
+        assert!(Jit::compile(&nodes, &Config{growth: TapeGrowth::Right, ..wrapping()}).is_none());
+        assert!(Jit::compile(&nodes, &Config{overflow: Overflow::Saturate, ..wrapping()}).is_none());
+        assert!(Jit::compile(&nodes, &Config::default()).is_none());
+        assert!(Jit::compile(&optimize(&parse(lex("+]")), &Optimizations::default()), &wrapping()).is_none());
+    }
+}
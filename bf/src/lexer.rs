@@ -0,0 +1,68 @@
+/// A single Brainfuck command, as found in the source text.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    IncrementPointer,
+    DecrementPointer,
+    Increment,
+    Decrement,
+    Write,
+    Read,
+    LoopBegin,
+    LoopEnd,
+    /// `#`, a place where a debugger may stop. It does nothing by itself.
+    Breakpoint,
+}
+
+/// Turns the source code into opcodes.
+///
+/// Every opcode is paired with the character offset it was read from, so the
+/// visualizer can highlight it. Anything that is not a command is a comment
+/// and is skipped.
+pub fn lex(source: &str) -> Vec<(OpCode, usize)> {
+    let mut operations = Vec::new();
+
+    for (cnt, symbol) in source.chars().enumerate() {
+        let op = match symbol {
+            '>' => Some((OpCode::IncrementPointer, cnt)),
+            '<' => Some((OpCode::DecrementPointer, cnt)),
+            '+' => Some((OpCode::Increment, cnt)),
+            '-' => Some((OpCode::Decrement, cnt)),
+            '.' => Some((OpCode::Write, cnt)),
+            ',' => Some((OpCode::Read, cnt)),
+            '[' => Some((OpCode::LoopBegin, cnt)),
+            ']' => Some((OpCode::LoopEnd, cnt)),
+            '#' => Some((OpCode::Breakpoint, cnt)),
+            _ => None
+        };
+
+        if let Some(op) = op {
+            operations.push(op);
+        }
+    }
+
+    operations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_comments() {
+        let ops = lex("a+ b[-]\n.");
+
+        assert_eq!(ops, vec![
+            (OpCode::Increment, 1),
+            (OpCode::LoopBegin, 4),
+            (OpCode::Decrement, 5),
+            (OpCode::LoopEnd, 6),
+            (OpCode::Write, 8),
+        ]);
+    }
+
+    #[test]
+    fn breakpoints() {
+        assert_eq!(lex("+#-"), vec![(OpCode::Increment, 0), (OpCode::Breakpoint, 1), (OpCode::Decrement, 2)]);
+    }
+}
@@ -0,0 +1,64 @@
+//! Brainfuck interpreter.
+//!
+//! The source is turned into opcodes by [`lex`], the opcodes into a tree of
+//! instructions by [`parse`], the instructions into [`Node`]s by [`optimize`],
+//! and the nodes into a flat [`Program`] by [`compile`]. The program is
+//! executed by [`run`], which reports every step as a [`CellChange`] so a
+//! front end can follow it, or by [`run_headless`] when nobody follows it.
+//! A run is paused, stepped or stopped through its [`ExecutionController`],
+//! which can also stop it at breakpoints and [`Watch`]es, and works on a
+//! [`Tape`] whose size comes from its [`Config`].
+//! The nodes can also be translated to other languages for a [`Target`], and
+//! on Linux x86-64 compiled to machine code by `Jit` for runs nobody watches.
+//!
+//! ```
+//! use std::sync::mpsc;
+//!
+//! let config = bf::Config::default();
+//! let nodes = bf::optimize(&bf::parse(bf::lex("++++++++[>++++++<-]>+.")), &config.optimizations);
+//! let program = bf::compile(&nodes);
+//! let mut tape = bf::Tape::new(&config);
+//! let (send_cell, receive_cell) = mpsc::channel();
+//! let (_send_data, receive_data) = mpsc::channel();
+//!
+//! let controller = bf::ExecutionController::new();
+//!
+//! bf::run(&program, &mut tape, send_cell, &receive_data, &controller);
+//!
+//! let output: Vec<u8> = receive_cell.iter()
+//!     .filter(|change| change.action == bf::Action::Output)
+//!     .map(|change| tape.cell_type().to_byte(change.content))
+//!     .collect();
+//! assert_eq!(output, b"1");
+//! ```
+
+mod bytecode;
+mod codegen;
+mod config;
+mod controller;
+mod diagnostics;
+mod executor;
+mod history;
+#[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+mod jit;
+mod lexer;
+mod optimizer;
+mod parser;
+mod span;
+mod tape;
+mod watch;
+
+pub use bytecode::{compile, Bytecode, Program};
+pub use codegen::{assemble, to_asm, to_c, to_rust, to_wasm, to_wat, Target};
+pub use config::{CellType, Config, Eof, Overflow, TapeGrowth, DEFAULT_TAPE_LENGTH};
+pub use controller::{Command, ExecutionController};
+pub use diagnostics::{check, Diagnostic};
+pub use executor::{run, run_headless, Action, CellChange};
+#[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+pub use jit::{Jit, JitError};
+pub use lexer::{lex, OpCode};
+pub use optimizer::{optimize, Idiom, Node, Op, Optimizations};
+pub use parser::{parse, Instruction, InstructionIndex};
+pub use span::{SourceMap, Span};
+pub use tape::Tape;
+pub use watch::{Comparison, Condition, Operand, Watch};
@@ -0,0 +1,392 @@
+extern crate bf;
+
+use bf::{assemble, check, compile, lex, optimize, parse, run_headless, Action, Config, ExecutionController, Node, Optimizations, Overflow, SourceMap, Span, Tape, Target};
+#[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+use bf::Jit;
+
+#[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+use std::cell::RefCell;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::io::{self, Read, Write};
+use std::process;
+use std::sync::mpsc;
+use std::thread;
+
+const USAGE: &str = "\
+usage: bf run [options] FILE
+       bf compile [options] [--target TARGET] [--link] [-o OUTPUT] FILE
+
+Runs a Brainfuck program, reading its input from stdin and writing its
+output to stdout. Exits with 1 if the brackets do not match or the program
+stops with an error, and with 2 for bad usage or options, a FILE that cannot
+be read, or output that cannot be written.
+
+On Linux x86-64 programs on a fixed tape of wrapping cells are compiled to
+machine code first, which makes long running ones much faster.
+
+compile translates the program instead, to OUTPUT or to stdout, and the
+translation behaves like bf run with the same options.
+
+options:
+    --tape-length N    cells on the tape (default 30000)
+    --growth MODE      fixed, right or both (default fixed)
+    --overflow MODE    error, wrap or saturate (default wrap)
+    --cell TYPE        u8, i8, u16, i16, u32 or i32 (default u8)
+    --eof MODE         what , does at the end of input: error, unchanged,
+                       zero or minus-one (default unchanged)
+    --no-optimize      run every command on its own instead of folding runs
+                       and recognising idioms like [-]
+    --no-jit           always run the program with the interpreter
+    --target TARGET    what compile translates to: c, rust, wat, wasm or
+                       x86_64-asm (default c)
+    --link             assemble and link x86_64-asm into an executable with
+                       as and ld, named after FILE unless -o is given
+    -o OUTPUT          the file compile writes to";
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+struct Options {
+    config: Config,
+    path: String,
+    target: Target,
+    output: Option<String>,
+    link: bool,
+    jit: bool,
+}
+
+//reads the options following the subcommand, compile says whether it is
+//bf compile so the options only it uses are refused for bf run
+fn parse_options(args: &[String], compile: bool) -> Result<Options, String> {
+    //most programs expect wrapping cells, the library stops with an error
+    let mut config = Config{overflow: Overflow::Wrap, ..Config::default()};
+    let mut path = None;
+    let mut target = Target::C;
+    let mut output = None;
+    let mut link = false;
+    let mut jit = true;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+
+        match arg.as_str() {
+            "--tape-length" => {
+                config.tape_length = match value()?.parse() {
+                    Ok(length) if length > 0 => length,
+                    _ => return Err(String::from("tape length must be a positive number"))
+                };
+            },
+            "--growth" => config.growth = value()?.parse()?,
+            "--overflow" => config.overflow = value()?.parse()?,
+            "--cell" => config.cell_type = value()?.parse()?,
+            "--eof" => config.eof = value()?.parse()?,
+            "--no-optimize" => config.optimizations = Optimizations::none(),
+            "--no-jit" => jit = false,
+            "--target" | "-o" | "--link" if !compile => return Err(format!("{} only applies to bf compile", arg)),
+            "--target" => target = value()?.parse()?,
+            "-o" => output = Some(value()?.clone()),
+            "--link" => link = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument {}", arg))
+        }
+    }
+
+    if link && target != Target::X86_64Asm {
+        return Err(String::from("--link needs --target x86_64-asm"));
+    }
+
+    match path {
+        Some(path) => Ok(Options{config, path, target, output, link, jit}),
+        None => Err(String::from("no program given"))
+    }
+}
+
+//reads the program, or gives the exit code if it cannot be read or its
+//brackets do not match
+fn read_source(path: &str) -> Result<String, i32> {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("bf: cannot read {}: {}", path, e);
+            return Err(2);
+        }
+    };
+
+    //nothing runs until every bracket is matched
+    let diagnostics = check(&source);
+    if !diagnostics.is_empty() {
+        for diagnostic in diagnostics {
+            eprintln!("Error: {}", diagnostic);
+        }
+        return Err(1);
+    }
+
+    Ok(source)
+}
+
+//runs the program and returns the exit code
+fn run_file(options: &Options) -> i32 {
+    let source = match read_source(&options.path) {
+        Ok(source) => source,
+        Err(code) => return code
+    };
+
+    let map = SourceMap::new(&source);
+    let nodes = optimize(&parse(lex(&source)), &options.config.optimizations);
+    if options.jit {
+        if let Some(exit_code) = run_native(&nodes, &options.config, &map) {
+            return exit_code;
+        }
+    }
+
+    let program = compile(&nodes);
+    let mut tape = Tape::new(&options.config);
+    let cell_type = options.config.cell_type;
+
+    let (send_cell, receive_cell) = mpsc::channel();
+    let (send_data, receive_data) = mpsc::channel();
+
+    let controller = ExecutionController::new();
+    let executor = controller.clone();
+    thread::spawn(move || {
+        run_headless(&program, &mut tape, send_cell, &receive_data, &executor);
+    });
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut output = io::BufWriter::new(stdout.lock());
+    let mut exit_code = 0;
+    //the first write that failed, after it the program is cancelled and the
+    //rest of its events are only drained
+    let mut failed = None;
+
+    for change in receive_cell {
+        match change.action {
+            Action::Output if failed.is_none() => {
+                failed = output.write_all(&[cell_type.to_byte(change.content)]).err();
+            },
+            Action::Input if failed.is_none() => {
+                //whatever was written so far may be a prompt
+                failed = output.flush().err();
+
+                let mut byte = [0];
+                //the executor applies the eof policy when it gets None
+                let value = match input.read(&mut byte) {
+                    Ok(1) => Some(byte[0] as i64),
+                    _ => None
+                };
+                send_data.send(value).unwrap();
+            },
+            Action::Error(e) if failed.is_none() => {
+                failed = output.flush().err();
+                report(&map, &e, change.span);
+                exit_code = 1;
+            },
+            Action::Input => send_data.send(None).unwrap(),
+            _ => ()
+        }
+        if failed.is_some() {
+            controller.cancel();
+        }
+    }
+
+    match failed.map_or_else(|| output.flush(), Err) {
+        Ok(()) => exit_code,
+        Err(e) => output_failed(e)
+    }
+}
+
+//runs the program compiled to machine code and returns the exit code, or
+//gives None if the jit cannot compile it for the options
+#[cfg(all(feature = "jit", target_os = "linux", target_arch = "x86_64"))]
+fn run_native(nodes: &[Node], config: &Config, map: &SourceMap) -> Option<i32> {
+    let jit = Jit::compile(nodes, config)?;
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    //both callbacks need the output, reading flushes it
+    let output = RefCell::new(io::BufWriter::new(io::stdout()));
+    //the first write that failed, the next . stops the program
+    let failed = RefCell::new(None);
+
+    let result = jit.run(
+        &mut || {
+            //whatever was written so far may be a prompt
+            if let Err(e) = output.borrow_mut().flush() {
+                failed.borrow_mut().get_or_insert(e);
+            }
+
+            let mut byte = [0];
+            match input.read(&mut byte) {
+                Ok(1) => Some(byte[0]),
+                _ => None
+            }
+        },
+        &mut |byte| {
+            if let Err(e) = output.borrow_mut().write_all(&[byte]) {
+                failed.borrow_mut().get_or_insert(e);
+            }
+            failed.borrow().is_none()
+        }
+    );
+
+    if let Some(e) = failed.into_inner() {
+        return Some(output_failed(e));
+    }
+    if let Err(e) = output.borrow_mut().flush() {
+        return Some(output_failed(e));
+    }
+    match result {
+        Ok(()) => Some(0),
+        Err(e) => {
+            report(map, &e.message, e.span);
+            Some(1)
+        }
+    }
+}
+
+#[cfg(not(all(feature = "jit", target_os = "linux", target_arch = "x86_64")))]
+fn run_native(_nodes: &[Node], _config: &Config, _map: &SourceMap) -> Option<i32> {
+    None
+}
+
+//prints why the output could not be written and gives the exit code
+fn output_failed(e: io::Error) -> i32 {
+    eprintln!("bf: cannot write the output: {}", e);
+    2
+}
+
+//prints an error the program stopped with
+fn report(map: &SourceMap, message: &str, span: Span) {
+    let (line, column) = map.position(span.start);
+    eprintln!("Error: {} (line {}, column {})\n{}", message, line, column, map.snippet(span));
+}
+
+//translates the program and returns the exit code
+fn compile_file(options: &Options) -> i32 {
+    let source = match read_source(&options.path) {
+        Ok(source) => source,
+        Err(code) => return code
+    };
+
+    let nodes = optimize(&parse(lex(&source)), &options.config.optimizations);
+    let translation = options.target.generate(&nodes, &options.config);
+
+    let written = if options.link {
+        //the assembly is text, so this cannot fail
+        let assembly = String::from_utf8(translation).unwrap();
+        let output = match &options.output {
+            Some(output) => Path::new(output).to_path_buf(),
+            None => Path::new(&options.path).with_extension("")
+        };
+        if output == Path::new(&options.path) {
+            Err(String::from("the executable would replace the program, name it with -o"))
+        }
+        else {
+            assemble(&assembly, &output)
+        }
+    }
+    else {
+        match &options.output {
+            Some(output) => fs::write(output, &translation).map_err(|e| format!("cannot write {}: {}", output, e)),
+            None => io::stdout().write_all(&translation).map_err(|e| format!("cannot write the translation: {}", e))
+        }
+    };
+    match written {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("bf: {}", e);
+            2
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let command: fn(&Options) -> i32 = match args.first().map(String::as_str) {
+        Some("run") => run_file,
+        Some("compile") => compile_file,
+        Some("-h") | Some("--help") => {
+            //a reader that stops early, like head, is not an error
+            let _ = writeln!(io::stdout(), "{}", USAGE);
+            return;
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match parse_options(&args[1..], args[0] == "compile") {
+        Ok(options) => process::exit(command(&options)),
+        Err(e) => {
+            eprintln!("bf: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bf::{CellType, Eof};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse_options(&args("hello.bf"), false).unwrap();
+
+        assert_eq!(options, Options{config: Config{overflow: Overflow::Wrap, ..Config::default()}, path: String::from("hello.bf"), target: Target::C, output: None, link: false, jit: true});
+    }
+
+    #[test]
+    fn all_options() {
+        let options = parse_options(&args("--tape-length 100 --overflow error --cell i16 --eof zero --no-optimize --no-jit hello.bf"), false).unwrap();
+
+        assert_eq!(options.config.tape_length, 100);
+        assert_eq!(options.config.overflow, Overflow::Error);
+        assert_eq!(options.config.cell_type, CellType::I16);
+        assert_eq!(options.config.eof, Eof::Zero);
+        assert_eq!(options.config.optimizations, Optimizations::none());
+        assert!(!options.jit);
+    }
+
+    #[test]
+    fn compile_options() {
+        let options = parse_options(&args("--target c -o hello.c --cell u16 hello.bf"), true).unwrap();
+
+        assert_eq!(options.target, Target::C);
+        assert_eq!(options.output, Some(String::from("hello.c")));
+        assert_eq!(options.config.cell_type, CellType::U16);
+
+        assert_eq!(parse_options(&args("--target wasm hello.bf"), true).unwrap().target, Target::Wasm);
+        assert!(parse_options(&args("--target x86_64-asm --link hello.bf"), true).unwrap().link);
+    }
+
+    #[test]
+    fn bad_options() {
+        assert!(parse_options(&args(""), false).is_err());
+        assert!(parse_options(&args("--cell"), false).is_err());
+        assert!(parse_options(&args("--tape-length 0 hello.bf"), false).is_err());
+        assert!(parse_options(&args("--speed 3 hello.bf"), false).is_err());
+        assert!(parse_options(&args("a.bf b.bf"), false).is_err());
+        assert!(parse_options(&args("--target cobol hello.bf"), true).is_err());
+        assert!(parse_options(&args("--link hello.bf"), true).is_err());
+    }
+
+    #[test]
+    fn compile_options_are_refused_by_run() {
+        assert!(parse_options(&args("--target c hello.bf"), false).is_err());
+        assert!(parse_options(&args("-o hello.c hello.bf"), false).is_err());
+        assert!(parse_options(&args("--target x86_64-asm --link hello.bf"), false).is_err());
+    }
+}
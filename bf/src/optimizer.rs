@@ -0,0 +1,286 @@
+use crate::parser::{Instruction, InstructionIndex};
+use crate::span::Span;
+
+/// An instruction of the program as it is executed.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum Op {
+    /// Adds to the current cell, a negative amount subtracts.
+    Add(i64),
+    /// Moves the data pointer, a negative amount moves it to the left.
+    Move(isize),
+    Write,
+    Read,
+    Loop(Vec<Node>),
+    /// A loop recognised as a common idiom. The body is kept for when the
+    /// idiom cannot be run in one step.
+    Idiom(Idiom, Vec<Node>),
+    /// A `#`, which stops the program when the controller has a breakpoint
+    /// there.
+    Breakpoint,
+    Error(String),
+}
+
+/// A loop that `optimize` knows the effect of without running it.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum Idiom {
+    /// `[-]` or `[+]`, which clear the current cell.
+    SetZero,
+    /// Loops like `[->+<]` or `[->++>+++<<]`, which add the current cell
+    /// times a factor to the cells at the given distances, and then clear it.
+    MultiplyAdd(Vec<(isize, i64)>),
+    /// `[>]` or `[<]`, which move by the given step until the current cell
+    /// is 0.
+    Scan(isize),
+}
+
+/// An `Op` together with the text offsets of every character it was made
+/// from, in source order. A loop has the offsets of its two brackets, and
+/// an idiom those of every character of its loop.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct Node {
+    pub op: Op,
+    pub offsets: Vec<usize>,
+}
+
+impl Node {
+    /// From the first character of the node to its last one.
+    pub fn span(&self) -> Span {
+        match (self.offsets.first(), self.offsets.last()) {
+            (Some(&first), Some(&last)) => Span::new(first, last + 1),
+            _ => Span::default()
+        }
+    }
+}
+
+/// Which passes `optimize` runs.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Optimizations {
+    /// Fold runs of the same command, like `+++` or `>>`, into one node.
+    pub fold: bool,
+    /// Turn clear, multiply and scan loops into `Op::Idiom`.
+    pub idioms: bool,
+}
+
+impl Optimizations {
+    /// Every instruction stays a node of its own.
+    pub fn none() -> Optimizations {
+        Optimizations {
+            fold: false,
+            idioms: false,
+        }
+    }
+}
+
+impl Default for Optimizations {
+    fn default() -> Optimizations {
+        Optimizations {
+            fold: true,
+            idioms: true,
+        }
+    }
+}
+
+/// Turns the parsed program into the nodes `run` executes.
+///
+/// Folding only joins runs of the same command, never `+-` or `><`, so a
+/// folded program overflows and leaves the tape at the same character as
+/// the unfolded one, whatever the `Overflow` and `TapeGrowth` settings.
+/// Idioms give the same result as their loop only when cells wrap, so with
+/// the other overflow policies `run` executes their body instead.
+pub fn optimize(instructions: &[InstructionIndex], optimizations: &Optimizations) -> Vec<Node> {
+    let mut program: Vec<Node> = Vec::new();
+
+    for instr in instructions {
+        if let Instruction::Loop(body) = &instr.code {
+            program.push(optimize_loop(body, instr.span, optimizations));
+            continue;
+        }
+
+        let op = match &instr.code {
+            Instruction::IncrementPointer => Op::Move(1),
+            Instruction::DecrementPointer => Op::Move(-1),
+            Instruction::Increment => Op::Add(1),
+            Instruction::Decrement => Op::Add(-1),
+            Instruction::Write => Op::Write,
+            Instruction::Read => Op::Read,
+            Instruction::Loop(_) => unreachable!(),
+            Instruction::Breakpoint => Op::Breakpoint,
+            Instruction::Error(e) => Op::Error(e.clone()),
+        };
+
+        if optimizations.fold {
+            if let Some(last) = program.last_mut() {
+                if fold(&mut last.op, &op) {
+                    last.offsets.push(instr.span.start);
+                    continue;
+                }
+            }
+        }
+
+        program.push(Node{op, offsets: vec![instr.span.start]});
+    }
+
+    program
+}
+
+//turns the loop into an idiom if it is one and idioms are wanted
+fn optimize_loop(body: &[InstructionIndex], span: Span, optimizations: &Optimizations) -> Node {
+    let (open, close) = (span.start, span.end - 1);
+    let body = optimize(body, optimizations);
+
+    match recognise(&body) {
+        Some(idiom) if optimizations.idioms => {
+            let mut offsets = vec![open];
+            offsets.extend(body.iter().flat_map(|node| node.offsets.iter().copied()));
+            offsets.push(close);
+
+            Node{op: Op::Idiom(idiom, body), offsets}
+        },
+        _ => Node{op: Op::Loop(body), offsets: vec![open, close]}
+    }
+}
+
+//adds next to last if both are steps in the same direction
+fn fold(last: &mut Op, next: &Op) -> bool {
+    match (last, next) {
+        (Op::Add(amount), Op::Add(step)) if amount.signum() == step.signum() => {
+            *amount += step;
+            true
+        },
+        (Op::Move(amount), Op::Move(step)) if amount.signum() == step.signum() => {
+            *amount += step;
+            true
+        },
+        _ => false
+    }
+}
+
+//the idiom a loop with this body is, if any
+fn recognise(body: &[Node]) -> Option<Idiom> {
+    if let [Node{op: Op::Move(step), ..}] = body {
+        return Some(Idiom::Scan(*step));
+    }
+
+    //what the body adds to each cell, by distance from where it started
+    let mut pointer = 0;
+    let mut changes: Vec<(isize, i64)> = Vec::new();
+
+    for node in body {
+        match node.op {
+            Op::Add(amount) => {
+                match changes.iter_mut().find(|(offset, _)| *offset == pointer) {
+                    Some((_, total)) => *total += amount,
+                    None => changes.push((pointer, amount))
+                }
+            },
+            Op::Move(amount) => pointer += amount,
+            _ => return None
+        }
+    }
+
+    //the loop must come back to the cell it counts with, and count it by one
+    if pointer != 0 {
+        return None;
+    }
+    match changes.iter().find(|(offset, _)| *offset == 0) {
+        Some((_, -1)) | Some((_, 1)) => (),
+        _ => return None
+    }
+
+    //a factor depends on whether the counter goes up or down
+    let step = changes.iter().find(|(offset, _)| *offset == 0).unwrap().1;
+    let targets: Vec<(isize, i64)> = changes.into_iter()
+        .filter(|&(offset, total)| offset != 0 && total != 0)
+        .map(|(offset, total)| (offset, -step * total))
+        .collect();
+
+    if targets.is_empty() {
+        Some(Idiom::SetZero)
+    }
+    else {
+        Some(Idiom::MultiplyAdd(targets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::parse;
+
+    fn ops(source: &str, optimizations: &Optimizations) -> Vec<Op> {
+        optimize(&parse(lex(source)), optimizations).into_iter().map(|node| node.op).collect()
+    }
+
+    #[test]
+    fn folds_runs() {
+        assert_eq!(ops("+++>>--<.", &Optimizations::default()), vec![Op::Add(3), Op::Move(2), Op::Add(-2), Op::Move(-1), Op::Write]);
+    }
+
+    #[test]
+    fn does_not_mix_directions() {
+        assert_eq!(ops("+-><", &Optimizations::default()), vec![Op::Add(1), Op::Add(-1), Op::Move(1), Op::Move(-1)]);
+    }
+
+    #[test]
+    fn breakpoints_split_runs() {
+        assert_eq!(ops("++#+", &Optimizations::default()), vec![Op::Add(2), Op::Breakpoint, Op::Add(1)]);
+    }
+
+    #[test]
+    fn keeps_every_offset() {
+        let program = optimize(&parse(lex("a++ +\n+[-]")), &Optimizations{idioms: false, ..Optimizations::default()});
+
+        assert_eq!(program[0].offsets, vec![1, 2, 4, 6]);
+        assert_eq!(program[0].span(), Span::new(1, 7));
+        assert_eq!(program[1].offsets, vec![7, 9]);
+        match &program[1].op {
+            Op::Loop(body) => assert_eq!(body[0].offsets, vec![8]),
+            other => panic!("expected a loop, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn no_folding() {
+        assert_eq!(ops("++", &Optimizations::none()), vec![Op::Add(1), Op::Add(1)]);
+    }
+
+    fn idiom(source: &str) -> Option<Idiom> {
+        match ops(source, &Optimizations::default()).pop() {
+            Some(Op::Idiom(idiom, _)) => Some(idiom),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn recognises_idioms() {
+        assert_eq!(idiom("[-]"), Some(Idiom::SetZero));
+        assert_eq!(idiom("[+]"), Some(Idiom::SetZero));
+        assert_eq!(idiom("[->+<]"), Some(Idiom::MultiplyAdd(vec![(1, 1)])));
+        assert_eq!(idiom("[->++>+++<<]"), Some(Idiom::MultiplyAdd(vec![(1, 2), (2, 3)])));
+        assert_eq!(idiom("[<-->+]"), Some(Idiom::MultiplyAdd(vec![(-1, 2)])));
+        assert_eq!(idiom("[>]"), Some(Idiom::Scan(1)));
+        assert_eq!(idiom("[<<]"), Some(Idiom::Scan(-2)));
+    }
+
+    #[test]
+    fn ordinary_loops() {
+        assert_eq!(idiom("[--]"), None);
+        assert_eq!(idiom("[->+]"), None);
+        assert_eq!(idiom("[-.]"), None);
+        assert_eq!(idiom("[-[-]]"), None);
+        assert_eq!(idiom("[-#]"), None);
+        assert_eq!(idiom("[]"), None);
+    }
+
+    #[test]
+    fn idioms_keep_every_offset() {
+        let program = optimize(&parse(lex("[- >+ <]")), &Optimizations::default());
+
+        assert_eq!(program[0].offsets, vec![0, 1, 3, 4, 6, 7]);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::lexer::OpCode;
+use crate::span::Span;
+
+/// A parsed instruction. Loops own their body.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub enum Instruction {
+    IncrementPointer,
+    DecrementPointer,
+    Increment,
+    Decrement,
+    Write,
+    Read,
+    Loop(Vec<InstructionIndex>),
+    Breakpoint,
+    Error(String)
+}
+
+/// An instruction together with the source it came from. The span of a
+/// loop runs from its `[` to its `]`, both included.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct InstructionIndex{
+    pub span: Span,
+    pub code: Instruction,
+}
+
+/// Turns the opcodes into instructions.
+///
+/// Unbalanced brackets do not fail the parse, they produce an
+/// `Instruction::Error` which is reported when execution reaches it. A loop
+/// that is never closed is replaced by its error, together with everything
+/// after it.
+pub fn parse(opcodes: Vec<(OpCode, usize)>) -> Vec<InstructionIndex> {
+    let mut program: Vec<InstructionIndex> = Vec::new();
+    //what came before each open loop, and the offset of its bracket, while
+    //program collects the body of the innermost one
+    let mut outer: Vec<(Vec<InstructionIndex>, usize)> = Vec::new();
+
+    for (op, txt_index) in opcodes {
+        let code = match op {
+            OpCode::IncrementPointer => Instruction::IncrementPointer,
+            OpCode::DecrementPointer => Instruction::DecrementPointer,
+            OpCode::Increment => Instruction::Increment,
+            OpCode::Decrement => Instruction::Decrement,
+            OpCode::Write => Instruction::Write,
+            OpCode::Read => Instruction::Read,
+            OpCode::Breakpoint => Instruction::Breakpoint,
+
+            OpCode::LoopBegin => {
+                outer.push((std::mem::take(&mut program), txt_index));
+                continue;
+            },
+
+            OpCode::LoopEnd => {
+                match outer.pop() {
+                    Some((before, loop_start)) => {
+                        let body = std::mem::replace(&mut program, before);
+                        program.push(InstructionIndex{span: Span::new(loop_start, txt_index + 1), code: Instruction::Loop(body)});
+                        continue;
+                    },
+                    None => Instruction::Error(format!("Loop without beginning at index {}", txt_index))
+                }
+            },
+        };
+
+        program.push(InstructionIndex{span: Span::at(txt_index), code});
+    }
+
+    if let Some((before, loop_start)) = outer.into_iter().next() {
+        program = before;
+        program.push(InstructionIndex{span: Span::at(loop_start), code: Instruction::Error(format!("Loop without ending at index {}", loop_start))});
+    }
+
+    program
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+
+    #[test]
+    fn nested_loops() {
+        let program = parse(lex("+[>[-]<]"));
+
+        assert_eq!(program.len(), 2);
+        assert_eq!(program[1].span, Span::new(1, 8));
+        match &program[1].code {
+            Instruction::Loop(body) => {
+                assert_eq!(body.len(), 3);
+                assert_eq!(body[1].span, Span::new(3, 6));
+            },
+            other => panic!("expected a loop, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn loop_without_beginning() {
+        let program = parse(lex("+]"));
+
+        assert_eq!(program[1].code, Instruction::Error(String::from("Loop without beginning at index 1")));
+    }
+
+    #[test]
+    fn loop_without_ending() {
+        let program = parse(lex("+[+"));
+
+        assert_eq!(program.last().unwrap().code, Instruction::Error(String::from("Loop without ending at index 1")));
+    }
+
+    #[test]
+    fn unclosed_loop_reports_the_outermost_bracket() {
+        let program = parse(lex("+ [[-]"));
+
+        assert_eq!(program.len(), 2);
+        assert_eq!(program[1], InstructionIndex{span: Span::at(2), code: Instruction::Error(String::from("Loop without ending at index 2"))});
+    }
+}
@@ -0,0 +1,124 @@
+//how many characters of the line are shown on each side of a snippet
+const SNIPPET_CONTEXT: usize = 30;
+
+/// A range of characters in the source, from `start` up to but not
+/// including `end`.
+///
+/// Offsets count characters, not bytes, the same way `lex` and the text
+/// views of GTK do.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span{start, end}
+    }
+
+    /// The single character at `offset`.
+    pub fn at(offset: usize) -> Span {
+        Span{start: offset, end: offset + 1}
+    }
+
+    pub fn len(self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.start >= self.end
+    }
+
+    /// From the start of this span to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span{start: self.start, end: other.end}
+    }
+}
+
+/// Turns spans into line and column positions and snippets of the source.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SourceMap {
+    lines: Vec<Vec<char>>,
+    //character offset of the first character of every line
+    starts: Vec<usize>,
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> SourceMap {
+        let mut lines = Vec::new();
+        let mut starts = Vec::new();
+        let mut start = 0;
+
+        for line in source.split('\n') {
+            let line: Vec<char> = line.chars().collect();
+            starts.push(start);
+            start += line.len() + 1;
+            lines.push(line);
+        }
+
+        SourceMap{lines, starts}
+    }
+
+    //index of the line with the character at offset
+    fn line_index(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        }
+    }
+
+    /// Line and column of the character at `offset`, both counted from 1.
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let index = self.line_index(offset);
+        (index + 1, offset - self.starts[index] + 1)
+    }
+
+    /// The line where `span` starts, indented, with carets under the span on
+    /// the next line. Long lines are cut down to the part around the span.
+    pub fn snippet(&self, span: Span) -> String {
+        let index = self.line_index(span.start);
+        let line = &self.lines[index];
+        let column = (span.start - self.starts[index]).min(line.len());
+        //a span going on to the next lines is only underlined on this one
+        let width = span.len().clamp(1, (line.len() - column).max(1));
+
+        let from = column.saturating_sub(SNIPPET_CONTEXT);
+        let to = (column + width + SNIPPET_CONTEXT).min(line.len());
+        let text: String = line[from..to].iter().map(|&c| if c == '\t' { ' ' } else { c }).collect();
+
+        format!("    {}\n    {}{}", text.trim_end_matches('\r'), " ".repeat(column - from), "^".repeat(width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let map = SourceMap::new("ab\ncd\n\nef");
+
+        assert_eq!(map.position(0), (1, 1));
+        assert_eq!(map.position(4), (2, 2));
+        assert_eq!(map.position(6), (3, 1));
+        assert_eq!(map.position(8), (4, 2));
+    }
+
+    #[test]
+    fn snippets() {
+        let map = SourceMap::new("comment\n  +++ [-]\n");
+
+        assert_eq!(map.snippet(Span::new(10, 13)), "      +++ [-]\n      ^^^");
+        assert_eq!(map.snippet(Span::at(14)), "      +++ [-]\n          ^");
+        assert_eq!(map.snippet(Span::new(2, 12)), "    comment\n      ^^^^^");
+    }
+
+    #[test]
+    fn joining() {
+        assert_eq!(Span::at(3).to(Span::at(7)), Span::new(3, 8));
+        assert_eq!(Span::new(3, 8).len(), 5);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::config::{CellType, Config, Eof, Overflow, TapeGrowth};
+
+/// The memory of a running program and its data pointer.
+///
+/// Cells are stored as `i64` whatever their `CellType`, and always hold a
+/// value in the range of that type.
+///
+/// Cell indices are relative to the cell the program started on, which is 0.
+/// Only a tape growing with `TapeGrowth::Both` has negative indices.
+#[derive(Debug)]
+#[derive(Clone, PartialEq)]
+pub struct Tape {
+    cells: Vec<i64>,
+    //position of cell 0 in cells
+    origin: usize,
+    //position of the data pointer in cells
+    pointer: usize,
+    overflow: Overflow,
+    cell_type: CellType,
+    growth: TapeGrowth,
+    eof: Eof,
+}
+
+impl Tape {
+    /// An empty tape as described by `config`, with the pointer on cell 0.
+    pub fn new(config: &Config) -> Tape {
+        Tape {
+            cells: vec![0; config.tape_length.max(1)],
+            origin: 0,
+            pointer: 0,
+            overflow: config.overflow,
+            cell_type: config.cell_type,
+            growth: config.growth,
+            eof: config.eof,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn cell_type(&self) -> CellType {
+        self.cell_type
+    }
+
+    /// All cells, from the leftmost one (see `first_index`) to the rightmost.
+    pub fn cells(&self) -> &[i64] {
+        &self.cells
+    }
+
+    /// Index of the leftmost cell, 0 unless the tape grew to the left.
+    pub fn first_index(&self) -> isize {
+        -(self.origin as isize)
+    }
+
+    /// Value of the cell at `index`, if the tape has reached it.
+    pub fn get(&self, index: isize) -> Option<i64> {
+        let position = index + self.origin as isize;
+
+        if position < 0 {
+            None
+        }
+        else {
+            self.cells.get(position as usize).copied()
+        }
+    }
+
+    /// Index of the cell under the data pointer.
+    pub fn pointer(&self) -> isize {
+        self.pointer as isize - self.origin as isize
+    }
+
+    /// Value of the cell under the data pointer.
+    pub fn current(&self) -> i64 {
+        self.cells[self.pointer]
+    }
+
+    //stores value in the current cell, wrapping it into range
+    pub(crate) fn set(&mut self, value: i64) {
+        self.cells[self.pointer] = self.cell_type.wrap(value);
+    }
+
+    //puts a value back into a cell, to undo a step, a cell the tape has not
+    //reached was never written
+    pub(crate) fn restore(&mut self, index: isize, value: i64) {
+        let position = index + self.origin as isize;
+
+        if position >= 0 {
+            if let Some(cell) = self.cells.get_mut(position as usize) {
+                *cell = value;
+            }
+        }
+    }
+
+    //puts the pointer back on a cell the tape has reached, to undo a step
+    pub(crate) fn move_to(&mut self, index: isize) {
+        self.pointer = (index + self.origin as isize) as usize;
+    }
+
+    //applies the end of input policy to the current cell,
+    //false if the policy is Eof::Error
+    pub(crate) fn end_of_input(&mut self) -> bool {
+        match self.eof {
+            Eof::Error => return false,
+            Eof::Unchanged => (),
+            Eof::Zero => self.set(0),
+            Eof::MinusOne => self.set(-1),
+        }
+        true
+    }
+
+    //true if adding never fails and never saturates
+    pub(crate) fn wraps(&self) -> bool {
+        self.overflow == Overflow::Wrap
+    }
+
+    //true if the pointer can move distance cells without leaving the tape
+    pub(crate) fn reaches(&self, distance: isize) -> bool {
+        let target = self.pointer() + distance;
+
+        match self.growth {
+            TapeGrowth::Fixed => target >= self.first_index() && target < self.first_index() + self.len() as isize,
+            TapeGrowth::Right => target >= self.first_index(),
+            TapeGrowth::Both => true,
+        }
+    }
+
+    //moves the pointer distance cells, negative to the left,
+    //false if it left the tape on the way
+    pub(crate) fn move_by(&mut self, distance: isize) -> bool {
+        for _ in 0..distance.unsigned_abs() {
+            let moved = if distance > 0 { self.move_right() } else { self.move_left() };
+            if !moved {
+                return false;
+            }
+        }
+        true
+    }
+
+    //adds amount to the current cell following the overflow policy,
+    //false if the policy is Overflow::Error and the result is out of range,
+    //in which case the cell is left at the limit it ran into like adding
+    //one at a time would
+    pub(crate) fn add(&mut self, amount: i64) -> bool {
+        let (min, max) = (self.cell_type.min(), self.cell_type.max());
+        let value = self.current() + amount;
+
+        let value = match self.overflow {
+            Overflow::Error => {
+                if value < min || value > max {
+                    self.cells[self.pointer] = value.clamp(min, max);
+                    return false;
+                }
+                value
+            },
+            Overflow::Wrap => self.cell_type.wrap(value),
+            Overflow::Saturate => value.clamp(min, max),
+        };
+
+        self.cells[self.pointer] = value;
+        true
+    }
+
+    //false if the pointer is already on the last cell and the tape cannot grow
+    pub(crate) fn move_right(&mut self) -> bool {
+        if self.pointer + 1 == self.cells.len() {
+            if self.growth == TapeGrowth::Fixed {
+                return false;
+            }
+            self.cells.push(0);
+        }
+
+        self.pointer += 1;
+        true
+    }
+
+    //false if the pointer is already on the first cell and the tape cannot grow
+    pub(crate) fn move_left(&mut self) -> bool {
+        if self.pointer == 0 {
+            if self.growth != TapeGrowth::Both {
+                return false;
+            }
+
+            //grow by as much as the tape already has, so that walking
+            //left does not shift all cells on every step
+            let extra = self.cells.len();
+            self.cells.splice(0..0, std::iter::repeat(0).take(extra));
+            self.origin += extra;
+            self.pointer += extra;
+        }
+
+        self.pointer -= 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tape(overflow: Overflow) -> Tape {
+        Tape::new(&Config{overflow, ..Config::default()})
+    }
+
+    fn wide_tape(overflow: Overflow, cell_type: CellType) -> Tape {
+        Tape::new(&Config{overflow, cell_type, ..Config::default()})
+    }
+
+    fn growing_tape(growth: TapeGrowth) -> Tape {
+        Tape::new(&Config{tape_length: 2, growth, ..Config::default()})
+    }
+
+    #[test]
+    fn strict_overflow() {
+        let mut tape = tape(Overflow::Error);
+
+        assert!(!tape.add(-1));
+        assert!(tape.add(255));
+        assert!(!tape.add(1));
+        assert_eq!(tape.current(), 255);
+    }
+
+    #[test]
+    fn wrapping_overflow() {
+        let mut tape = tape(Overflow::Wrap);
+
+        assert!(tape.add(-1));
+        assert_eq!(tape.current(), 255);
+        assert!(tape.add(3));
+        assert_eq!(tape.current(), 2);
+    }
+
+    #[test]
+    fn saturating_overflow() {
+        let mut tape = tape(Overflow::Saturate);
+
+        assert!(tape.add(-1));
+        assert_eq!(tape.current(), 0);
+        assert!(tape.add(300));
+        assert_eq!(tape.current(), 255);
+    }
+
+    #[test]
+    fn wide_cells() {
+        let mut tape = wide_tape(Overflow::Error, CellType::U16);
+
+        assert!(tape.add(256));
+        assert_eq!(tape.current(), 256);
+        assert!(!tape.add(65280));
+        assert_eq!(tape.current(), 65535);
+
+        let mut tape = wide_tape(Overflow::Wrap, CellType::I16);
+
+        assert!(tape.add(32768));
+        assert_eq!(tape.current(), -32768);
+    }
+
+    #[test]
+    fn signed_saturation() {
+        let mut tape = wide_tape(Overflow::Saturate, CellType::I8);
+
+        assert!(tape.add(-200));
+        assert_eq!(tape.current(), -128);
+    }
+
+    #[test]
+    fn input_is_wrapped() {
+        let mut tape = wide_tape(Overflow::Error, CellType::I8);
+
+        tape.set(200);
+        assert_eq!(tape.current(), -56);
+    }
+
+    #[test]
+    fn fixed_tape_does_not_grow() {
+        let mut tape = growing_tape(TapeGrowth::Fixed);
+
+        assert!(tape.move_right());
+        assert!(!tape.move_right());
+        assert_eq!(tape.len(), 2);
+    }
+
+    #[test]
+    fn tape_grows_to_the_right() {
+        let mut tape = growing_tape(TapeGrowth::Right);
+
+        for _ in 0..10 {
+            assert!(tape.move_right());
+        }
+        assert_eq!(tape.pointer(), 10);
+        assert_eq!(tape.len(), 11);
+        assert!(!growing_tape(TapeGrowth::Right).move_left());
+    }
+
+    #[test]
+    fn tape_grows_to_the_left() {
+        let mut tape = growing_tape(TapeGrowth::Both);
+        tape.add(7);
+
+        for _ in 0..5 {
+            assert!(tape.move_left());
+        }
+        tape.add(3);
+
+        assert_eq!(tape.pointer(), -5);
+        assert_eq!(tape.get(-5), Some(3));
+        assert_eq!(tape.get(0), Some(7));
+        assert!(tape.first_index() <= -5);
+        assert_eq!(tape.cells()[(-5 - tape.first_index()) as usize], 3);
+    }
+
+    #[test]
+    fn reach() {
+        let mut tape = growing_tape(TapeGrowth::Fixed);
+
+        assert!(tape.reaches(1));
+        assert!(!tape.reaches(2));
+        assert!(!tape.reaches(-1));
+        assert!(tape.move_by(1));
+        assert!(tape.reaches(-1));
+        assert!(growing_tape(TapeGrowth::Right).reaches(5));
+        assert!(growing_tape(TapeGrowth::Both).reaches(-5));
+    }
+
+    #[test]
+    fn restore_skips_cells_not_reached() {
+        let mut tape = growing_tape(TapeGrowth::Right);
+
+        tape.restore(-1, 5);
+        tape.restore(2, 5);
+        tape.restore(1, 5);
+        assert_eq!(tape.cells(), &[0, 5]);
+    }
+}
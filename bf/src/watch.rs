@@ -0,0 +1,246 @@
+use crate::tape::Tape;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Something a `Condition` compares.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// `cell[N]`, the cell at index N. Cells the tape has not grown to yet
+    /// count as 0.
+    Cell(isize),
+    /// `cell`, the cell under the data pointer.
+    Current,
+    /// `ptr`, the index of the data pointer.
+    Pointer,
+    Number(i64),
+}
+
+/// How the two sides of a `Condition` are compared.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+//in the order they are searched for, so <= is not taken for <
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    ("<=", Comparison::LessOrEqual),
+    (">=", Comparison::GreaterOrEqual),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+/// A comparison on the tape and the data pointer, like `cell[3] == 10` or
+/// `ptr > 20`.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub left: Operand,
+    pub comparison: Comparison,
+    pub right: Operand,
+}
+
+/// Something about the tape to stop the program at, checked by `run` after
+/// every instruction that changes the tape or moves the data pointer.
+#[derive(Debug)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    /// Stops when the condition becomes true.
+    Condition(Condition),
+    /// `write cell[N]`, stops whenever the cell at index N is written, even
+    /// when it keeps its value.
+    Write(isize),
+    /// `change cell[N]`, stops whenever the value of the cell at index N
+    /// changes.
+    Change(isize),
+}
+
+impl Operand {
+    fn value(self, tape: &Tape) -> i64 {
+        match self {
+            Operand::Cell(index) => tape.get(index).unwrap_or(0),
+            Operand::Current => tape.current(),
+            Operand::Pointer => tape.pointer() as i64,
+            Operand::Number(number) => number,
+        }
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, tape: &Tape) -> bool {
+        let (left, right) = (self.left.value(tape), self.right.value(tape));
+
+        match self.comparison {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Operand, String> {
+        let text = text.trim();
+
+        if text == "ptr" {
+            return Ok(Operand::Pointer);
+        }
+        if text == "cell" {
+            return Ok(Operand::Current);
+        }
+        if let Some(index) = text.strip_prefix("cell").and_then(|rest| rest.trim_start().strip_prefix('[')).and_then(|rest| rest.strip_suffix(']')) {
+            return index.trim().parse().map(Operand::Cell).map_err(|_| format!("invalid cell index '{}'", index.trim()));
+        }
+
+        text.parse().map(Operand::Number).map_err(|_| format!("unknown operand '{}', expected cell, cell[N], ptr or a number", text))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Condition, String> {
+        for (symbol, comparison) in COMPARISONS.iter() {
+            if let Some(at) = text.find(symbol) {
+                return Ok(Condition {
+                    left: text[..at].parse()?,
+                    comparison: *comparison,
+                    right: text[at + symbol.len()..].parse()?,
+                });
+            }
+        }
+
+        Err(format!("'{}' compares nothing, expected one of == != < <= > >=", text.trim()))
+    }
+}
+
+impl FromStr for Watch {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Watch, String> {
+        let text = text.trim();
+
+        let cell = |rest: &str| match rest.parse()? {
+            Operand::Cell(index) => Ok(index),
+            _ => Err(format!("expected cell[N] after a watch kind, got '{}'", rest.trim()))
+        };
+
+        if let Some(rest) = text.strip_prefix("write ") {
+            cell(rest).map(Watch::Write)
+        }
+        else if let Some(rest) = text.strip_prefix("change ") {
+            cell(rest).map(Watch::Change)
+        }
+        else {
+            text.parse().map(Watch::Condition)
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Cell(index) => write!(f, "cell[{}]", index),
+            Operand::Current => write!(f, "cell"),
+            Operand::Pointer => write!(f, "ptr"),
+            Operand::Number(number) => write!(f, "{}", number),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = COMPARISONS.iter().find(|(_, comparison)| *comparison == self.comparison).unwrap().0;
+        write!(f, "{} {} {}", self.left, symbol, self.right)
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Watch::Condition(condition) => write!(f, "{}", condition),
+            Watch::Write(index) => write!(f, "write cell[{}]", index),
+            Watch::Change(index) => write!(f, "change cell[{}]", index),
+        }
+    }
+}
+
+//a watch with what it saw at the last check, None until the first one
+#[derive(Debug)]
+pub(crate) struct Watcher {
+    pub(crate) watch: Watch,
+    last: Option<i64>,
+}
+
+impl Watcher {
+    pub(crate) fn new(watch: Watch) -> Watcher {
+        Watcher{watch, last: None}
+    }
+
+    //what triggered, if the watch did after the cells at written were written
+    pub(crate) fn check(&mut self, tape: &Tape, written: &[isize]) -> Option<String> {
+        match self.watch {
+            Watch::Condition(condition) => {
+                let holds = condition.holds(tape) as i64;
+                let before = self.last.replace(holds);
+
+                if holds == 1 && before == Some(0) { Some(condition.to_string()) } else { None }
+            },
+            Watch::Write(index) => {
+                if written.contains(&index) { Some(format!("cell {} written", index)) } else { None }
+            },
+            Watch::Change(index) => {
+                let value = tape.get(index).unwrap_or(0);
+                let before = self.last.replace(value);
+
+                match before {
+                    Some(before) if before != value => Some(format!("cell {} changed to {}", index, value)),
+                    _ => None
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_watches() {
+        assert_eq!("cell[3] == 10".parse(), Ok(Watch::Condition(Condition{left: Operand::Cell(3), comparison: Comparison::Equal, right: Operand::Number(10)})));
+        assert_eq!("ptr>20".parse(), Ok(Watch::Condition(Condition{left: Operand::Pointer, comparison: Comparison::Greater, right: Operand::Number(20)})));
+        assert_eq!("cell <= cell[ -1 ]".parse(), Ok(Watch::Condition(Condition{left: Operand::Current, comparison: Comparison::LessOrEqual, right: Operand::Cell(-1)})));
+        assert_eq!("write cell[0]".parse(), Ok(Watch::Write(0)));
+        assert_eq!("change cell[7]".parse(), Ok(Watch::Change(7)));
+    }
+
+    #[test]
+    fn rejects_nonsense() {
+        assert!("cell[3]".parse::<Watch>().is_err());
+        assert!("cell[x] == 1".parse::<Watch>().is_err());
+        assert!("ptr == tape".parse::<Watch>().is_err());
+        assert!("write ptr".parse::<Watch>().is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        for text in ["cell[3] == 10", "ptr != cell", "cell[-2] >= 0", "write cell[1]", "change cell[4]"] {
+            assert_eq!(text.parse::<Watch>().unwrap().to_string(), text);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use gtk::prelude::*;
+
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+//width of the gutter left of the source, in pixels
+const GUTTER_WIDTH: i32 = 16;
+
+//columns of storeBreakpoints
+const ENABLED_COLUMN: u32 = 0;
+const LOCATION_COLUMN: u32 = 1;
+
+//called with the offsets of the enabled breakpoints
+type Listener = Box<dyn Fn(Vec<usize>)>;
+
+//a breakpoint is a mark in the source buffer, so it moves along with the
+//text typed or deleted before it
+struct Breakpoint {
+    mark: gtk::TextMark,
+    enabled: bool,
+}
+
+/// The breakpoints of the source editor, set by clicking in the gutter left
+/// of `txtInput` or by writing a `#`. They are listed in `treeBreakpoints`,
+/// where they can be turned off or removed.
+#[derive(Clone)]
+pub struct Breakpoints {
+    view: gtk::TextView,
+    list: gtk::TreeView,
+    store: gtk::ListStore,
+    //in source order, which is also the order of the rows of the list
+    breakpoints: Rc<RefCell<Vec<Breakpoint>>>,
+    listeners: Rc<RefCell<Vec<Listener>>>,
+}
+
+impl Breakpoints {
+    pub fn from_builder(builder: &gtk::Builder) -> Breakpoints {
+        let breakpoints = Breakpoints {
+            view: builder.get_object("txtInput").unwrap(),
+            list: builder.get_object("treeBreakpoints").unwrap(),
+            store: builder.get_object("storeBreakpoints").unwrap(),
+            breakpoints: Rc::new(RefCell::new(Vec::new())),
+            listeners: Rc::new(RefCell::new(Vec::new())),
+        };
+        let enabled: gtk::CellRendererToggle = builder.get_object("rendererBreakpointEnabled").unwrap();
+        let remove_button: gtk::Button = builder.get_object("btnRemoveBreakpoint").unwrap();
+
+        breakpoints.view.set_border_window_size(gtk::TextWindowType::Left, GUTTER_WIDTH);
+
+        let copy = breakpoints.clone();
+        breakpoints.view.connect_draw(move |view, cr| {
+            let gutter = match TextViewExt::get_window(view, gtk::TextWindowType::Left) {
+                Some(gutter) if gtk::cairo_should_draw_window(cr, &gutter) => gutter,
+                _ => return Inhibit(false)
+            };
+            let buffer = view.get_buffer().unwrap();
+
+            cr.save();
+            gtk::cairo_transform_to_window(cr, view, &gutter);
+
+            //a filled dot for every breakpoint that is on, a ring for the others
+            for breakpoint in copy.breakpoints.borrow().iter() {
+                let (y, height) = view.get_line_yrange(&buffer.get_iter_at_mark(&breakpoint.mark));
+                let (_, y) = view.buffer_to_window_coords(gtk::TextWindowType::Left, 0, y);
+
+                cr.new_path();
+                cr.arc(GUTTER_WIDTH as f64 / 2.0, y as f64 + height as f64 / 2.0, GUTTER_WIDTH as f64 / 4.0, 0.0, 2.0 * PI);
+                if breakpoint.enabled {
+                    cr.set_source_rgb(0.8, 0.1, 0.1);
+                    cr.fill();
+                }
+                else {
+                    cr.set_source_rgb(0.5, 0.5, 0.5);
+                    cr.set_line_width(1.5);
+                    cr.stroke();
+                }
+            }
+
+            cr.restore();
+            Inhibit(false)
+        });
+
+        let copy = breakpoints.clone();
+        breakpoints.view.connect_button_press_event(move |view, event| {
+            let gutter = TextViewExt::get_window(view, gtk::TextWindowType::Left);
+            if gutter.is_none() || event.get_window() != gutter {
+                return Inhibit(false);
+            }
+
+            let (_, y) = event.get_position();
+            let (_, y) = view.window_to_buffer_coords(gtk::TextWindowType::Left, 0, y as i32);
+            copy.toggle_line(view.get_line_at_y(y).0.get_line());
+            Inhibit(true)
+        });
+
+        let copy = breakpoints.clone();
+        breakpoints.view.get_buffer().unwrap().connect_changed(move |_| copy.update());
+
+        let copy = breakpoints.clone();
+        enabled.connect_toggled(move |_, path| {
+            if let Some(&row) = path.get_indices().first() {
+                if let Some(breakpoint) = copy.breakpoints.borrow_mut().get_mut(row as usize) {
+                    breakpoint.enabled = !breakpoint.enabled;
+                }
+                copy.update();
+            }
+        });
+
+        let copy = breakpoints.clone();
+        remove_button.connect_clicked(move |_| {
+            let selected = copy.list.get_selection().get_selected()
+                .and_then(|(model, iter)| model.get_path(&iter))
+                .and_then(|path| path.get_indices().first().copied());
+
+            let mark = selected.and_then(|row| copy.breakpoints.borrow().get(row as usize).map(|breakpoint| breakpoint.mark.clone()));
+            if let Some(mark) = mark {
+                copy.remove(&mark);
+            }
+        });
+
+        breakpoints.update();
+        breakpoints
+    }
+
+    fn buffer(&self) -> gtk::TextBuffer {
+        self.view.get_buffer().unwrap()
+    }
+
+    /// Text offsets of the breakpoints that are on, in order.
+    pub fn offsets(&self) -> Vec<usize> {
+        let buffer = self.buffer();
+
+        self.breakpoints.borrow().iter()
+            .filter(|breakpoint| breakpoint.enabled)
+            .map(|breakpoint| buffer.get_iter_at_mark(&breakpoint.mark).get_offset() as usize)
+            .collect()
+    }
+
+    /// Calls `listener` with the new `offsets` whenever a breakpoint is set,
+    /// removed, turned on or off, or moved by an edit.
+    pub fn connect_changed<F: Fn(Vec<usize>) + 'static>(&self, listener: F) {
+        self.listeners.borrow_mut().push(Box::new(listener));
+    }
+
+    /// Removes the breakpoints on `line`, counted from 0, or sets one on its
+    /// first command if it has none.
+    pub fn toggle_line(&self, line: i32) {
+        let buffer = self.buffer();
+        let on_line: Vec<gtk::TextMark> = self.breakpoints.borrow().iter()
+            .filter(|breakpoint| buffer.get_iter_at_mark(&breakpoint.mark).get_line() == line)
+            .map(|breakpoint| breakpoint.mark.clone())
+            .collect();
+
+        if !on_line.is_empty() {
+            for mark in &on_line {
+                self.remove(mark);
+            }
+            return;
+        }
+
+        let mut iter = buffer.get_iter_at_line(line);
+        while iter.get_line() == line && !iter.is_end() {
+            if iter.get_char().is_some_and(is_command) {
+                //with right gravity the mark stays in front of its command
+                //when something is typed just before it
+                let mark = buffer.create_mark(None, &iter, false).unwrap();
+                self.breakpoints.borrow_mut().push(Breakpoint{mark, enabled: true});
+                self.update();
+                return;
+            }
+            iter.forward_char();
+        }
+    }
+
+    //removing the breakpoint of a # deletes the #, otherwise it would come
+    //back with the next edit; while a program runs the source cannot change,
+    //so the breakpoint is only turned off then
+    fn remove(&self, mark: &gtk::TextMark) {
+        let buffer = self.buffer();
+        let mut start = buffer.get_iter_at_mark(mark);
+        let is_hash = start.get_char() == Some('#');
+
+        if is_hash && !self.view.get_editable() {
+            for breakpoint in self.breakpoints.borrow_mut().iter_mut().filter(|breakpoint| &breakpoint.mark == mark) {
+                breakpoint.enabled = false;
+            }
+            self.update();
+            return;
+        }
+
+        self.breakpoints.borrow_mut().retain(|breakpoint| &breakpoint.mark != mark);
+        buffer.delete_mark(mark);
+
+        if is_hash {
+            let mut end = start.clone();
+            end.forward_char();
+            buffer.delete(&mut start, &mut end);
+        }
+        self.update();
+    }
+
+    //drops the breakpoints whose command was deleted, gives every # one,
+    //and shows them in the list and the gutter
+    fn update(&self) {
+        let buffer = self.buffer();
+        let offset = |breakpoint: &Breakpoint| buffer.get_iter_at_mark(&breakpoint.mark).get_offset();
+
+        {
+            let mut breakpoints = self.breakpoints.borrow_mut();
+            breakpoints.sort_by_key(|breakpoint| offset(breakpoint));
+
+            //marks of deleted text end up on the next character, which may
+            //not be a command or may already have a breakpoint
+            let mut previous = None;
+            breakpoints.retain(|breakpoint| {
+                let iter = buffer.get_iter_at_mark(&breakpoint.mark);
+                let keep = iter.get_char().is_some_and(is_command) && previous != Some(iter.get_offset());
+
+                previous = Some(iter.get_offset());
+                if !keep {
+                    buffer.delete_mark(&breakpoint.mark);
+                }
+                keep
+            });
+
+            let text = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false).unwrap();
+            let set: Vec<i32> = breakpoints.iter().map(&offset).collect();
+
+            for (index, _) in text.as_str().chars().enumerate().filter(|&(_, symbol)| symbol == '#') {
+                if !set.contains(&(index as i32)) {
+                    let mark = buffer.create_mark(None, &buffer.get_iter_at_offset(index as i32), false).unwrap();
+                    breakpoints.push(Breakpoint{mark, enabled: true});
+                }
+            }
+            breakpoints.sort_by_key(|breakpoint| offset(breakpoint));
+        }
+
+        self.store.clear();
+        for breakpoint in self.breakpoints.borrow().iter() {
+            let iter = buffer.get_iter_at_mark(&breakpoint.mark);
+            let location = format!("line {}, column {}", iter.get_line() + 1, iter.get_line_offset() + 1);
+
+            self.store.insert_with_values(None, &[ENABLED_COLUMN, LOCATION_COLUMN], &[&breakpoint.enabled, &location]);
+        }
+        self.view.queue_draw();
+
+        let offsets = self.offsets();
+        for listener in self.listeners.borrow().iter() {
+            listener(offsets.clone());
+        }
+    }
+}
+
+fn is_command(symbol: char) -> bool {
+    "<>+-.,[]#".contains(symbol)
+}
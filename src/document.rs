@@ -0,0 +1,227 @@
+use gtk::prelude::*;
+use gio::prelude::*;
+
+use gtk::{ButtonsType, DialogFlags, FileChooserAction, MessageDialog, MessageType, ResponseType};
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const TITLE: &str = "Brainfuck Visualizer";
+
+/// The program in the source editor and the file it was loaded from.
+///
+/// Whether there are unsaved changes is the modified flag of the buffer, so
+/// every edit is tracked without any extra bookkeeping.
+#[derive(Clone)]
+pub struct Document {
+    window: gtk::Window,
+    buffer: gtk::TextBuffer,
+    path: Rc<RefCell<Option<PathBuf>>>,
+}
+
+impl Document {
+    pub fn new(window: &gtk::Window, buffer: &gtk::TextBuffer) -> Document {
+        let document = Document {
+            window: window.clone(),
+            buffer: buffer.clone(),
+            path: Rc::new(RefCell::new(None)),
+        };
+
+        let document_copy = document.clone();
+        buffer.connect_modified_changed(move |_| {
+            document_copy.update_title();
+        });
+
+        document.update_title();
+        document
+    }
+
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.borrow().clone()
+    }
+
+    fn update_title(&self) {
+        let name = match self.path() {
+            Some(path) => path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            None => String::from("Untitled"),
+        };
+        let modified = if self.buffer.get_modified() { "*" } else { "" };
+
+        self.window.set_title(&format!("{}{} - {}", modified, name, TITLE));
+    }
+
+    /// Starts an empty, untitled program.
+    pub fn new_file(&self) {
+        if !self.confirm_discard() {
+            return;
+        }
+
+        self.buffer.set_text("");
+        self.buffer.set_modified(false);
+        self.path.replace(None);
+        self.update_title();
+    }
+
+    /// Asks for a file and loads it.
+    pub fn open(&self) {
+        if !self.confirm_discard() {
+            return;
+        }
+
+        let dialog = self.file_chooser("Open", FileChooserAction::Open, "_Open");
+        let path = if dialog.run() == ResponseType::Accept { dialog.get_filename() } else { None };
+        dialog.close();
+
+        if let Some(path) = path {
+            self.load(&path);
+        }
+    }
+
+    /// Opens `uri` as picked from the recent files menu.
+    pub fn open_uri(&self, uri: &str) {
+        if !self.confirm_discard() {
+            return;
+        }
+
+        if let Some(path) = gio::File::new_for_uri(uri).get_path() {
+            self.load(&path);
+        }
+    }
+
+    /// Replaces the program with the contents of `path`. Returns false and
+    /// tells the user if the file cannot be read.
+    pub fn load(&self, path: &Path) -> bool {
+        match fs::read_to_string(path) {
+            Ok(source) => {
+                self.buffer.set_text(&source);
+                self.buffer.set_modified(false);
+                self.path.replace(Some(path.to_path_buf()));
+                self.update_title();
+                add_recent(path);
+                true
+            },
+            Err(e) => {
+                self.show_error(&format!("Cannot open {}: {}", path.display(), e));
+                false
+            }
+        }
+    }
+
+    /// Saves to the file the program came from, or asks for one if it is new.
+    /// Returns false if nothing was saved.
+    pub fn save(&self) -> bool {
+        match self.path() {
+            Some(path) => self.write(&path),
+            None => self.save_as(),
+        }
+    }
+
+    /// Asks for a file and saves the program to it.
+    pub fn save_as(&self) -> bool {
+        let dialog = self.file_chooser("Save As", FileChooserAction::Save, "_Save");
+        dialog.set_do_overwrite_confirmation(true);
+        match self.path() {
+            Some(path) => {
+                dialog.set_filename(&path);
+            },
+            None => dialog.set_current_name("untitled.bf"),
+        }
+
+        let path = if dialog.run() == ResponseType::Accept { dialog.get_filename() } else { None };
+        dialog.close();
+
+        match path {
+            Some(path) => self.write(&path),
+            None => false
+        }
+    }
+
+    fn write(&self, path: &Path) -> bool {
+        let source = self.buffer.get_text(&self.buffer.get_start_iter(), &self.buffer.get_end_iter(), false).unwrap();
+
+        match fs::write(path, source.as_str()) {
+            Ok(()) => {
+                self.path.replace(Some(path.to_path_buf()));
+                self.buffer.set_modified(false);
+                self.update_title();
+                add_recent(path);
+                true
+            },
+            Err(e) => {
+                self.show_error(&format!("Cannot save {}: {}", path.display(), e));
+                false
+            }
+        }
+    }
+
+    /// Offers to save unsaved changes before they are thrown away. Returns
+    /// false if the user cancelled.
+    pub fn confirm_discard(&self) -> bool {
+        if !self.buffer.get_modified() {
+            return true;
+        }
+
+        let dialog = MessageDialog::new(Some(&self.window), DialogFlags::MODAL, MessageType::Question, ButtonsType::None, "The program has unsaved changes. Save them first?");
+        dialog.add_button("Close _without saving", ResponseType::No);
+        dialog.add_button("_Cancel", ResponseType::Cancel);
+        dialog.add_button("_Save", ResponseType::Yes);
+        dialog.set_default_response(ResponseType::Yes);
+
+        let response = dialog.run();
+        dialog.close();
+
+        match response {
+            ResponseType::Yes => self.save(),
+            ResponseType::No => true,
+            _ => false
+        }
+    }
+
+    fn file_chooser(&self, title: &str, action: FileChooserAction, accept: &str) -> gtk::FileChooserDialog {
+        let dialog = gtk::FileChooserDialog::with_buttons(Some(title), Some(&self.window), action, &[("_Cancel", ResponseType::Cancel), (accept, ResponseType::Accept)]);
+
+        let programs = gtk::FileFilter::new();
+        programs.set_name(Some("Brainfuck programs"));
+        programs.add_pattern("*.bf");
+        programs.add_pattern("*.b");
+        dialog.add_filter(&programs);
+
+        let all = gtk::FileFilter::new();
+        all.set_name(Some("All files"));
+        all.add_pattern("*");
+        dialog.add_filter(&all);
+
+        dialog
+    }
+
+    fn show_error(&self, message: &str) {
+        let dialog = MessageDialog::new(Some(&self.window), DialogFlags::MODAL, MessageType::Error, ButtonsType::Ok, message);
+        dialog.run();
+        dialog.close();
+    }
+}
+
+//makes the file show up in the Open Recent menu
+fn add_recent(path: &Path) {
+    if let Some(manager) = gtk::RecentManager::get_default() {
+        manager.add_item(&gio::File::new_for_path(path).get_uri());
+    }
+}
+
+/// A menu with the most recently opened or saved programs.
+pub fn recent_menu() -> gtk::RecentChooserMenu {
+    let menu = gtk::RecentChooserMenu::new();
+
+    let filter = gtk::RecentFilter::new();
+    filter.add_pattern("*.bf");
+    filter.add_pattern("*.b");
+    menu.set_filter(Some(&filter));
+
+    menu.set_show_not_found(false);
+    menu.set_sort_type(gtk::RecentSortType::Mru);
+    menu.set_limit(10);
+
+    menu
+}
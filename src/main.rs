@@ -1,381 +1,41 @@
 extern crate gtk;
 extern crate gio;
+extern crate bf;
+
+mod breakpoints;
+mod document;
+mod program_input;
+mod settings;
+mod tape_view;
+mod timeline;
+mod watches;
 
 use gtk::prelude::*;
 
 use gtk::{ButtonsType, DialogFlags, MessageType, MessageDialog, Window};
 
-use std::sync::mpsc;
-use std::{thread, time};
-use std::process;
-
-use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
-
-#[derive(Debug)]
-#[derive(Clone)]
-enum OpCode {
-    IncrementPointer,
-    DecrementPointer,
-    Increment,
-    Decrement,
-    Write,
-    Read,
-    LoopBegin,
-    LoopEnd,
-}
-
-#[derive(Debug)]
-#[derive(Clone)]
-enum Instruction {
-    IncrementPointer,
-    DecrementPointer,
-    Increment,
-    Decrement,
-    Write,
-    Read,
-    Loop(Vec<InstructionIndex>),
-    Error(String)
-}
-
-#[derive(Debug)]
-#[derive(Clone)]
-enum Action {
-    Input,
-    Output,
-    Tape,
-    Error(String)
-}
-
-#[derive(Debug)]
-#[derive(Clone)]
-struct CellChange{
-    index: usize,
-    content: u8,
-    action: Action,
-    text_index: usize
-}
-
-#[derive(Debug)]
-#[derive(Clone)]
-struct InstructionIndex{
-    index: usize,
-    code: Instruction,
-}
-
-static RESET: AtomicBool = AtomicBool::new(false);
-static PAUSE: AtomicBool = AtomicBool::new(false);
-static DELAY: AtomicU16 = AtomicU16::new(500);
-
-//turns the source code into opcodes
-fn lex(source: String) -> Vec<(OpCode, usize)> {
-    let mut operations = Vec::new();
-    let mut cnt = 0;
-
-    for symbol in source.chars() {
-        let op = match symbol {
-            '>' => Some((OpCode::IncrementPointer, cnt)),
-            '<' => Some((OpCode::DecrementPointer, cnt)),
-            '+' => Some((OpCode::Increment, cnt)),
-            '-' => Some((OpCode::Decrement, cnt)),
-            '.' => Some((OpCode::Write, cnt)),
-            ',' => Some((OpCode::Read, cnt)),
-            '[' => Some((OpCode::LoopBegin, cnt)),
-            ']' => Some((OpCode::LoopEnd, cnt)),
-            _ => None
-        };
-
-        match op {
-            Some(op) => operations.push(op),
-            None => ()
-        }
-        cnt+=1;
-    }
-
-    operations
-}
-
-//turns the opcodes into instructions
-fn parse(opcodes: Vec<(OpCode, usize)>) -> Vec<InstructionIndex> {
-    let mut program: Vec<InstructionIndex> = Vec::new();
-    let mut loop_stack = 0;
-    let mut loop_start = 0;
-
-    for (i, op) in opcodes.iter().enumerate() {
-        if loop_stack == 0 {
-            let instr = match op {
-
-                (OpCode::IncrementPointer, txt_index) => {
-                    Some(InstructionIndex{index: *txt_index, code: Instruction::IncrementPointer})                 
-                },
-
-                (OpCode::DecrementPointer, txt_index)=> {
-                    Some(InstructionIndex{index: *txt_index, code: Instruction::DecrementPointer})
-                },
-                
-                (OpCode::Increment, txt_index) => {
-                    Some(InstructionIndex{index: *txt_index, code: Instruction::Increment})
-                },
-
-                (OpCode::Decrement, txt_index) => {
-                    Some(InstructionIndex{index: *txt_index, code: Instruction::Decrement})
-                },
-
-                (OpCode::Write, txt_index) => {
-                    Some(InstructionIndex{index: *txt_index, code: Instruction::Write})
-                },
-
-                (OpCode::Read, txt_index) => {
-                    Some(InstructionIndex{index: *txt_index, code: Instruction::Read})
-                },
-
-                (OpCode::LoopBegin, _) => {
-                    loop_start = i;
-                    loop_stack += 1;
-                    None
-                },
-
-                (OpCode::LoopEnd, txt_index) => {
-                    Some(InstructionIndex{index: *txt_index, code: Instruction::Error(format!("Loop without beginning at index {}", txt_index))})
-                },
-            };
-
-            match instr{
-                Some(instr) => program.push(instr),
-                None => ()
-            }
-        } 
-        else {
-            match op {
-                (OpCode::LoopBegin,_) => {
-                    loop_stack += 1;
-                },
-                (OpCode::LoopEnd, _) => {
-                    loop_stack -= 1;
-
-                    if loop_stack == 0 {
-                        program.push(InstructionIndex{index: i, code: Instruction::Loop(parse(opcodes[loop_start+1..i].to_vec()))});
-                    }
-                },
-                _ => (),
-            }
-        }
-    }
-
-    if loop_stack != 0 {
-        program.push(InstructionIndex{index: loop_start, code: Instruction::Error(format!("Loop without ending at index {}", loop_start))});
-    }
-
-    program
-}
-
-//runs the parsed program
-fn run(instructions: &Vec<InstructionIndex>, tape: &mut Vec<u8>, data_pointer: &mut usize, send_cell: std::sync::mpsc::Sender<CellChange>, receive_data: &std::sync::mpsc::Receiver<i16>) {
-    for instr in instructions {
-        if RESET.load(Ordering::Relaxed) {
-            break;
-        }
-
-        if PAUSE.load(Ordering::Relaxed) {
-            loop {
-                if PAUSE.load(Ordering::Relaxed) == false{
-                    break;
-                }
-                if RESET.load(Ordering::Relaxed) {
-                    return;
-                }
-            }
-        }
-        thread::sleep(time::Duration::from_millis(DELAY.load(Ordering::Relaxed) as u64));
-        match instr {
-            
-            InstructionIndex{index: i, code: Instruction::IncrementPointer} => {
-                if RESET.load(Ordering::Relaxed) {
-                    break;
-                }
-
-                if *data_pointer < 31 {
-                    *data_pointer += 1;
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Tape, text_index: *i}).unwrap();
-                } 
-                else {
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Error(String::from(format!("Data pointer out of bounds at index {}", *i))), text_index: *i}).unwrap();
-                    RESET.store(true, Ordering::Relaxed);
-                    break;
-                }
-
-                if PAUSE.load(Ordering::Relaxed) {
-                    loop {
-                        if PAUSE.load(Ordering::Relaxed) == false{
-                            break;
-                        }
-                        if RESET.load(Ordering::Relaxed) {
-                            return;
-                        }
-                    }
-                }
-            },
+use bf::{check, compile, lex, optimize, parse, run, Action, CellChange, CellType, Command, Config, ExecutionController, Span, Tape};
 
-            InstructionIndex{index: i, code: Instruction::DecrementPointer} => {
-                if RESET.load(Ordering::Relaxed) {
-                    break;
-                }
+use breakpoints::Breakpoints;
+use document::Document;
+use program_input::ProgramInput;
+use settings::Settings;
+use tape_view::TapeView;
+use timeline::{Timeline, HISTORY_LIMIT};
+use watches::Watches;
 
-                if *data_pointer >0 {
-                    *data_pointer -= 1;
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Tape, text_index: *i}).unwrap();
-                }
-                else {
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Error(String::from(format!("Data pointer out of bounds at index {}", *i))), text_index: *i}).unwrap();
-                    RESET.store(true, Ordering::Relaxed);
-                    break;
-                }
-
-                if PAUSE.load(Ordering::Relaxed) {
-                    loop {
-                        if PAUSE.load(Ordering::Relaxed) == false{
-                            break;
-                        }
-                        if RESET.load(Ordering::Relaxed) {
-                            return;
-                        }
-                    }
-                }
-            },
-
-            InstructionIndex{index: i, code: Instruction::Increment} => {
-                if RESET.load(Ordering::Relaxed) {
-                    break;
-                }
-
-                if tape[*data_pointer] < 255 {
-                    tape[*data_pointer] += 1;
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Tape, text_index: *i}).unwrap();
-                }
-                else {
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Error(String::from(format!("Addition overflow at index {}", *i))), text_index: *i}).unwrap();
-                    RESET.store(true, Ordering::Relaxed);
-                    break;
-                }
-                if PAUSE.load(Ordering::Relaxed) {
-                    loop {
-                        if PAUSE.load(Ordering::Relaxed) == false{
-                            break;
-                        }
-                        if RESET.load(Ordering::Relaxed) {
-                            return;
-                        }
-                    }
-                }
-            },
-
-            InstructionIndex{index: i, code: Instruction::Decrement} => {
-                if RESET.load(Ordering::Relaxed) {
-                    break;
-                }
-                if tape[*data_pointer] > 0 {
-                    tape[*data_pointer] -= 1;
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Tape, text_index: *i}).unwrap();
-                }
-                else {
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Error(String::from(format!("Subtraction underflow at index {}", *i))), text_index: *i}).unwrap();
-                    RESET.store(true, Ordering::Relaxed);
-                    break;
-                }
-                if PAUSE.load(Ordering::Relaxed) {
-                    loop {
-                        if PAUSE.load(Ordering::Relaxed) == false{
-                            break;
-                        }
-                        if RESET.load(Ordering::Relaxed) {
-                            return;
-                        }
-                    }
-                }
-            },
-
-            InstructionIndex{index: i, code: Instruction::Write} => {
-                if RESET.load(Ordering::Relaxed) {
-                    break;
-                }
-                send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Output, text_index: *i}).unwrap();
-                if PAUSE.load(Ordering::Relaxed) {
-                    loop {
-                        if PAUSE.load(Ordering::Relaxed) == false{
-                            break;
-                        }
-                        if RESET.load(Ordering::Relaxed) {
-                            return;
-                        }
-                    }
-                }
-            },
-
-            InstructionIndex{index: i, code: Instruction::Read} => {    
-                if RESET.load(Ordering::Relaxed) {
-                    break;
-                }                  
-                send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Input, text_index: *i}).unwrap();
-                if PAUSE.load(Ordering::Relaxed) {
-                    loop {
-                        if PAUSE.load(Ordering::Relaxed) == false{
-                            break;
-                        }
-                        if RESET.load(Ordering::Relaxed) {
-                            return;
-                        }
-                    }
-                }
-                let input = receive_data.recv().unwrap();
-                if input != -1 {
-                    tape[*data_pointer] = input as u8;
-                } 
-                else {
-                    send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Error(String::from(format!("invalid input at index {}", *i))), text_index: *i}).unwrap();
-                    RESET.store(true, Ordering::Relaxed);
-                    break;
-                }
-            },
-
-            InstructionIndex{index: _, code: Instruction::Loop(nested_instructions)} => {
-                if RESET.load(Ordering::Relaxed) {
-                    break;
-                }
-                while tape[*data_pointer] != 0 {
-                    run(&nested_instructions, tape, data_pointer, send_cell.clone(), &receive_data);
-                    if PAUSE.load(Ordering::Relaxed) {
-                        loop {
-                            if PAUSE.load(Ordering::Relaxed) == false{
-                                break;
-                            }
-                            if RESET.load(Ordering::Relaxed) {
-                                return;
-                            }
-                        }
-                    }
-                    if RESET.load(Ordering::Relaxed) {
-                        break;
-                    }
-                }
-            },
-
-            InstructionIndex{index: i, code: Instruction::Error(e)} => {    
-                send_cell.send(CellChange{index: *data_pointer, content: tape[*data_pointer], action: Action::Error(e.clone()), text_index: *i}).unwrap();
-                RESET.store(true, Ordering::Relaxed);
-                break;
-            }
-        }
-    }
-}
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::env;
+use std::path::Path;
+use std::process;
 
 //does the parsing and visualizing
-fn start_parsing(tape_lbls: &Vec<gtk::Label>, marker_lbls: &Vec<gtk::Label>, input: &gtk::TextView, output: &gtk::TextView, speed_slider: &gtk::Scale){
+fn start_parsing(tape_view: &TapeView, input: &gtk::TextView, output: &gtk::TextView, program_input: &ProgramInput, speed_slider: &gtk::Scale, config: &Config, controller: &ExecutionController){
 
-    RESET.store(false, Ordering::Relaxed);
-    
-    for i in 0..32 {
-        tape_lbls[i].set_text("0");
-        marker_lbls[i].set_text("");
-    }
+    tape_view.clear(config.tape_length);
 
     input.set_editable(false);
     input.set_cursor_visible(false);
@@ -387,34 +47,64 @@ fn start_parsing(tape_lbls: &Vec<gtk::Label>, marker_lbls: &Vec<gtk::Label>, inp
     let source_buffer = input.get_buffer().unwrap();
     let source = source_buffer.get_text(&source_buffer.get_start_iter(), &source_buffer.get_end_iter(), false);
 
-    let opcodes = lex(String::from(source.as_ref().unwrap().as_str()));
-    let program = parse(opcodes);
+    in_buf.remove_tag_by_name("mismatch", &in_buf.get_start_iter(), &in_buf.get_end_iter());
 
-    let mut tape: Vec<u8> = vec![0; 32];
-    let mut data_pointer = 0;
+    //every unmatched bracket is shown at once, and nothing runs until they are fixed
+    let diagnostics = check(source.as_ref().unwrap().as_str());
+    if !diagnostics.is_empty() {
+        let messages: Vec<String> = diagnostics.iter().map(|diagnostic| format!("Error: {}", diagnostic)).collect();
+        buf.set_text(&messages.join("\n"));
+
+        for diagnostic in &diagnostics {
+            let span = diagnostic.span;
+            in_buf.apply_tag_by_name("mismatch", &in_buf.get_iter_at_offset(span.start as i32), &in_buf.get_iter_at_offset(span.end as i32));
+        }
+
+        input.set_editable(true);
+        input.set_cursor_visible(true);
+        return;
+    }
+
+    let mut stdin = match program_input.bytes() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            buf.set_text(&format!("Error: {}", e));
+            input.set_editable(true);
+            input.set_cursor_visible(true);
+            return;
+        }
+    };
+
+    let mut input_ended = false;
+
+    let opcodes = lex(source.as_ref().unwrap().as_str());
+    let program = compile(&optimize(&parse(opcodes), &config.optimizations));
+
+    let mut tape = Tape::new(config);
+    let cell_type = config.cell_type;
 
     let (send_cell, receive_cell) = mpsc::channel();
     let (send_data, receive_data) = mpsc::channel();
 
 
-    thread::spawn(move || {
+    let run_controller = controller.clone();
 
-        run(&program, &mut tape, &mut data_pointer, send_cell, &receive_data);
+    thread::spawn(move || {
 
-        return;
+        run(&program, &mut tape, send_cell, &receive_data, &run_controller);
     });
     loop { 
-        DELAY.store(speed_slider.get_value()as u16* 10, Ordering::Relaxed);
+        controller.set_delay(speed_slider.get_value()as u16* 10);
 
         let received = receive_cell.try_recv();
 
         match received.clone(){
-            Ok(CellChange{index: _, content: _, action: Action::Output, text_index: i}) => {
+            Ok(CellChange{index: _, content: _, action: Action::Output, span}) => {
 
                 let mut output_txt = String::from(buf.get_text(&buf.get_start_iter(), &buf.get_end_iter(), false).unwrap().as_str());
-                in_buf.select_range(&in_buf.get_iter_at_offset(i as i32), &in_buf.get_iter_at_offset(i as i32 +1));
+                select_span(&in_buf, span);
 
-                output_txt.push(received.unwrap().content as char);
+                output_txt.push(cell_type.to_byte(received.unwrap().content) as char);
                 buf.set_text(output_txt.as_str());
 
                 while gtk::events_pending(){
@@ -422,76 +112,89 @@ fn start_parsing(tape_lbls: &Vec<gtk::Label>, marker_lbls: &Vec<gtk::Label>, inp
                 }
             },
 
-            Ok(CellChange{index: _, content: _, action: Action::Tape, text_index: i}) => {
+            Ok(CellChange{index: _, content: _, action: Action::Tape, span}) => {
 
-                for i in 0..32 {
-                    marker_lbls[i].set_text("");
+                let change = received.unwrap();
+                tape_view.set_step("");
+                tape_view.update(change.index, change.content);
+                select_span(&in_buf, span);
+                while gtk::events_pending(){
+                    gtk::main_iteration();
                 }
+            },
+
+            Ok(CellChange{index, content, action: Action::Idiom(label), span}) => {
 
-                marker_lbls[received.clone().unwrap().index].set_text("#");
-                let tmp = received.clone().unwrap().content.to_string();
-                in_buf.select_range(&in_buf.get_iter_at_offset(i as i32), &in_buf.get_iter_at_offset(i as i32 +1));
-                tape_lbls[received.clone().unwrap().index].set_text(&tmp[..]);
+                tape_view.set_step(&label);
+                tape_view.update(index, content);
+                select_span(&in_buf, span);
                 while gtk::events_pending(){
                     gtk::main_iteration();
                 }
             },
 
-            Ok(CellChange{index: _, content: _, action: Action::Input, text_index: i}) => {
+            Ok(CellChange{index, content, action: Action::Breakpoint, span}) => {
 
-                for i in 0..32 {
-                    marker_lbls[i].set_text("");
+                tape_view.set_step("stopped at breakpoint");
+                tape_view.update(index, content);
+                select_span(&in_buf, span);
+                while gtk::events_pending(){
+                    gtk::main_iteration();
                 }
+            },
 
-                marker_lbls[received.clone().unwrap().index].set_text("#");
-                let tmp = received.clone().unwrap().content.to_string();
-                in_buf.select_range(&in_buf.get_iter_at_offset(i as i32), &in_buf.get_iter_at_offset(i as i32 +1));
-                tape_lbls[received.clone().unwrap().index].set_text(&tmp[..]);
-
-                let dialog_window = MessageDialog::new(None::<&Window>, DialogFlags::empty(), MessageType::Info, ButtonsType::Ok, "Input:");
-
-                let dialog_box = dialog_window.get_content_area();
-                let user_entry = gtk::Entry::new();
-
-                user_entry.set_size_request(250,0);
-                dialog_box.pack_end(&user_entry, false, false, 0);
-
-                dialog_window.show_all();
-
-                let response = dialog_window.run();
-                let text = user_entry.get_text();
-
-                let number = text.parse::<i64>();
-
-                match number{
-                    Ok(_) => {
-                        if number.clone().unwrap() > 255 || number.clone().unwrap() < 0{
-                            send_data.send(-1).unwrap();
-                        }
-                        else {
-                            send_data.send(number.unwrap() as i16).unwrap();
-                        }
-                    },
-                    Err(_) => {
-                        if (response == gtk::ResponseType::Ok)  && (text.len() == 1){
-                            send_data.send(text.as_bytes()[0] as i16).unwrap();
-                        }
-                        else {
-                            send_data.send(-1).unwrap();
-                        }
-                    }
+            Ok(CellChange{index, content, action: Action::Watch(label), span}) => {
+
+                tape_view.set_step(&format!("stopped: {}", label));
+                tape_view.update(index, content);
+                select_span(&in_buf, span);
+                while gtk::events_pending(){
+                    gtk::main_iteration();
+                }
+            },
+
+            Ok(CellChange{index, content, action: Action::Rewound(outputs), span}) => {
+
+                //what the undone instructions wrote goes away again
+                let mut end = buf.get_end_iter();
+                buf.delete(&mut buf.get_iter_at_offset(outputs as i32), &mut end);
+
+                tape_view.set_step("stepped back");
+                tape_view.update(index, content);
+                select_span(&in_buf, span);
+                while gtk::events_pending(){
+                    gtk::main_iteration();
                 }
+            },
 
-                dialog_window.close();
+            Ok(CellChange{index: _, content: _, action: Action::Input, span}) => {
+
+                let change = received.unwrap();
+                tape_view.update(change.index, change.content);
+                select_span(&in_buf, span);
+
+                //the input pane is read first, the dialog is only for when it runs out,
+                //and once the user ended the input every read gets the end of input
+                let value = match stdin.pop_front() {
+                    Some(byte) => Some(byte as i64),
+                    None if input_ended => None,
+                    None => {
+                        let value = ask_for_input(cell_type);
+                        input_ended = value.is_none();
+                        value
+                    }
+                };
+                send_data.send(value).unwrap();
                 
                 while gtk::events_pending(){
                     gtk::main_iteration();
                 }
             },
 
-            Ok(CellChange{index: _, content: _, action: Action::Error(e), text_index: _}) => {
+            Ok(CellChange{index: _, content: _, action: Action::Error(e), span}) => {
 
                 let output_txt = format!("Error: {}", e);
+                select_span(&in_buf, span);
 
                 buf.set_text(output_txt.as_str());
 
@@ -518,14 +221,54 @@ fn start_parsing(tape_lbls: &Vec<gtk::Label>, marker_lbls: &Vec<gtk::Label>, inp
     input.set_cursor_visible(true);
 }
 
-fn reset_app(tape_lbls: &Vec<gtk::Label>, marker_lbls: &Vec<gtk::Label>, input: &gtk::TextView, output: &gtk::TextView){
+//highlights the characters of span in the source
+fn select_span(buffer: &gtk::TextBuffer, span: Span) {
+    buffer.select_range(&buffer.get_iter_at_offset(span.start as i32), &buffer.get_iter_at_offset(span.end as i32));
+}
 
-    RESET.store(true, Ordering::Relaxed);
+//asks for a single number or character until it gets a valid one,
+//None if the user ends the input instead
+fn ask_for_input(cell_type: CellType) -> Option<i64> {
+    let dialog_window = MessageDialog::new(None::<&Window>, DialogFlags::empty(), MessageType::Info, ButtonsType::None, "Input:");
+    dialog_window.add_button("_End of input", gtk::ResponseType::Close);
+    dialog_window.add_button("_OK", gtk::ResponseType::Ok);
+    dialog_window.set_default_response(gtk::ResponseType::Ok);
 
-    for i in 0..32 {
-        tape_lbls[i].set_text("0");
-        marker_lbls[i].set_text("");
-    }
+    let dialog_box = dialog_window.get_content_area();
+    let user_entry = gtk::Entry::new();
+
+    user_entry.set_size_request(250,0);
+    user_entry.set_activates_default(true);
+    dialog_box.pack_end(&user_entry, false, false, 0);
+
+    dialog_window.show_all();
+
+    let value = loop {
+        if dialog_window.run() != gtk::ResponseType::Ok {
+            break None;
+        }
+
+        let text = user_entry.get_text();
+
+        match text.parse::<i64>() {
+            Ok(number) if number >= cell_type.min() && number <= cell_type.max() => break Some(number),
+            Err(_) if text.len() == 1 => break Some(text.as_bytes()[0] as i64),
+            _ => {
+                dialog_window.set_property_secondary_text(Some(&format!("Enter a number from {} to {} or a single character.", cell_type.min(), cell_type.max())));
+                user_entry.grab_focus();
+            }
+        }
+    };
+
+    dialog_window.close();
+    value
+}
+
+fn reset_app(tape_view: &TapeView, input: &gtk::TextView, output: &gtk::TextView, controller: &ExecutionController){
+
+    controller.send(Command::Cancel);
+
+    tape_view.reset();
 
     let buf: gtk::TextBuffer = output.get_buffer().unwrap();
     let in_buf: gtk::TextBuffer = input.get_buffer().unwrap();
@@ -538,11 +281,214 @@ fn reset_app(tape_lbls: &Vec<gtk::Label>, marker_lbls: &Vec<gtk::Label>, input:
     }
 }
 
+fn main() {
+
+    if gtk::init().is_err() {
+        println!("Failed to initialize GTK.");
+        return;
+    }
+
+    let glade_src = include_str!("../GUI.glade");
+    let builder = gtk::Builder::from_string(glade_src);
+    let window: gtk::Window = builder.get_object("window").unwrap();
+    let start_button: gtk::Button= builder.get_object("btnStart").unwrap();
+    let pause_button: gtk::Button = builder.get_object("btnPause").unwrap();
+    let step_button: gtk::Button = builder.get_object("btnStep").unwrap();
+    let step_back_button: gtk::Button = builder.get_object("btnStepBack").unwrap();
+    let step_over_button: gtk::Button = builder.get_object("btnStepOver").unwrap();
+    let finish_iteration_button: gtk::Button = builder.get_object("btnFinishIteration").unwrap();
+    let step_out_button: gtk::Button = builder.get_object("btnStepOut").unwrap();
+    let run_to_cursor_button: gtk::Button = builder.get_object("btnRunToCursor").unwrap();
+    let reset_button: gtk::Button = builder.get_object("btnReset").unwrap();
+    let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
+    let input: gtk::TextView = builder.get_object("txtInput").unwrap();
+    let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
+    let menu_bar: gtk::MenuBar = builder.get_object("menuBar").unwrap();
+    let new_item: gtk::MenuItem = builder.get_object("menuNew").unwrap();
+    let open_item: gtk::MenuItem = builder.get_object("menuOpen").unwrap();
+    let recent_item: gtk::MenuItem = builder.get_object("menuRecent").unwrap();
+    let save_item: gtk::MenuItem = builder.get_object("menuSave").unwrap();
+    let save_as_item: gtk::MenuItem = builder.get_object("menuSaveAs").unwrap();
+    let quit_item: gtk::MenuItem = builder.get_object("menuQuit").unwrap();
+    let settings = Settings::from_builder(&builder);
+    let program_input = ProgramInput::from_builder(&builder);
+    let tape_view = TapeView::from_builder(&builder);
+    let breakpoints = Breakpoints::from_builder(&builder);
+    let watches = Watches::from_builder(&builder);
+    let timeline = Timeline::from_builder(&builder);
+    let document = Document::new(&window, &input.get_buffer().unwrap());
+
+    tape_view.clear(settings.config().tape_length);
+
+    //a program can be given on the command line
+    if let Some(path) = env::args().nth(1) {
+        document.load(Path::new(&path));
+    }
+
+    let recent_menu = document::recent_menu();
+    recent_item.set_submenu(Some(&recent_menu));
+
+    window.show_all();
+
+    let document_copy = document.clone();
+    new_item.connect_activate(move |_| document_copy.new_file());
+
+    let document_copy = document.clone();
+    open_item.connect_activate(move |_| document_copy.open());
+
+    let document_copy = document.clone();
+    recent_menu.connect_item_activated(move |menu| {
+        if let Some(uri) = menu.get_current_uri() {
+            document_copy.open_uri(&uri);
+        }
+    });
+
+    let document_copy = document.clone();
+    save_item.connect_activate(move |_| {
+        document_copy.save();
+    });
+
+    let document_copy = document.clone();
+    save_as_item.connect_activate(move |_| {
+        document_copy.save_as();
+    });
+
+    let window_copy = window.clone();
+    quit_item.connect_activate(move |_| window_copy.close());
+
+    //closing goes through here, so unsaved changes are never lost silently
+    window.connect_delete_event(move |_, _| {
+        gtk::Inhibit(!document.confirm_discard())
+    });
+
+    let tape_view_copy = tape_view.clone();
+    let input_copy = input.clone();
+    let output_copy = output.clone();
+
+    //controls the program that is currently running, replaced on every start
+    let session = Rc::new(RefCell::new(ExecutionController::new()));
+    let session_start = session.clone();
+    let session_reset = session.clone();
+    let session_pause = session.clone();
+    let session_commands = session.clone();
+    let session_run_to = session.clone();
+    let session_watches = session.clone();
+    let session_seek = session.clone();
+    let session_timeline = session.clone();
+
+    //breakpoints and watches can be changed while the program runs
+    breakpoints.connect_changed(move |offsets| session.borrow().set_breakpoints(offsets));
+    watches.connect_changed(move |enabled| session_watches.borrow().set_watches(enabled));
+
+    let tape_view_copy_2 = tape_view;
+    let input_copy_2 = input.clone();
+    let output_copy_2 = output.clone();
+
+    let timeline_start = timeline.clone();
+
+    start_button.connect_clicked(move |but| {
+
+        but.set_sensitive(false);
+
+        let controller = ExecutionController::new();
+        if session_start.borrow().is_paused() {
+            controller.pause();
+        }
+        controller.set_breakpoints(breakpoints.offsets());
+        controller.set_watches(watches.enabled());
+        controller.set_history_limit(HISTORY_LIMIT);
+        controller.set_idiom_labels(true);
+        session_start.replace(controller.clone());
+        timeline_start.clear();
+
+        settings.set_sensitive(false);
+        program_input.set_sensitive(false);
+        menu_bar.set_sensitive(false);
+        start_parsing(&tape_view_copy, &input_copy, &output_copy, &program_input, &speed_slider, &settings.config(), &controller);
+        menu_bar.set_sensitive(true);
+        program_input.set_sensitive(true);
+        settings.set_sensitive(true);
+        but.set_sensitive(true);
+
+    });
+
+    reset_button.connect_clicked(move |_|{
+
+        input_copy_2.set_editable(true);
+        input_copy_2.set_cursor_visible(true);
+        
+        reset_app(&tape_view_copy_2, &input_copy_2, &output_copy_2, &session_reset.borrow());
+
+    });   
+
+    pause_button.connect_clicked(move |_|{
+
+        let controller = session_pause.borrow();
+
+        if !controller.is_paused() {
+            controller.send(Command::Pause);
+        }
+        else{
+            controller.send(Command::Resume);
+        }
+
+    });
+
+    //these buttons only tell the running program what to do next
+    let commands = [
+        (step_button, Command::Step(1)),
+        (step_over_button, Command::StepOver),
+        (finish_iteration_button, Command::FinishIteration),
+        (step_out_button, Command::StepOut),
+        (step_back_button, Command::StepBack(1)),
+    ];
+    for (button, command) in commands.iter() {
+        let session = session_commands.clone();
+        let command = *command;
+        button.connect_clicked(move |_| session.borrow().send(command));
+    }
+
+    run_to_cursor_button.connect_clicked(move |_|{
+
+        let buffer = input.get_buffer().unwrap();
+        let cursor = buffer.get_iter_at_mark(&buffer.get_insert().unwrap());
+        session_run_to.borrow().send(Command::RunTo(cursor.get_offset() as usize));
+
+    });
+
+    //the slider goes back by undoing instructions and forward by stepping
+    timeline.connect_seek(move |target| {
+        let controller = session_seek.borrow();
+        let length = controller.history_len();
+
+        if target < length {
+            controller.send(Command::StepBack(length - target));
+        }
+        else {
+            controller.send(Command::Step(target - length));
+        }
+    });
+
+    //the run does not know about the slider, so it follows the history
+    glib::timeout_add_local(100, move || {
+        timeline.refresh(&session_timeline.borrow());
+        glib::Continue(true)
+    });
+
+    window.connect_destroy(|_| {
+        process::exit(0);
+    });
+
+    gtk::main();
+
+}
+
 //----------------------------------------------------------------TESTS START HERE-------------------------------------------------------------
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bf::TapeGrowth;
 
     #[test]
     fn normal_parse() {
@@ -555,15 +501,14 @@ mod tests {
         let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
         let input: gtk::TextView = builder.get_object("txtInput").unwrap();
         let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-        let tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-        let marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
+        let tape_view = TapeView::from_builder(&builder);
 
         let source_buffer = input.get_buffer().unwrap();
         source_buffer.set_text("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.");
 
         speed_slider.set_value(0.0);
         
-        start_parsing(&tape_lbls, &marker_lbls, &input, &output, &speed_slider);
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config::default(), &ExecutionController::new());
         
         let out_buffer = output.get_buffer().unwrap();
 
@@ -581,19 +526,23 @@ mod tests {
         let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
         let input: gtk::TextView = builder.get_object("txtInput").unwrap();
         let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-        let tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-        let marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
+        let tape_view = TapeView::from_builder(&builder);
 
         let source_buffer = input.get_buffer().unwrap();
         source_buffer.set_text("++++++++>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.");
 
         speed_slider.set_value(0.0);
         
-        start_parsing(&tape_lbls, &marker_lbls, &input, &output, &speed_slider);
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config::default(), &ExecutionController::new());
         
         let out_buffer = output.get_buffer().unwrap();
 
-        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "Error: Loop without beginning at index 47")
+        let out_text = String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap());
+        assert!(out_text.starts_with("Error: Loop without beginning at line 1, column 48\n"));
+
+        let mismatch = source_buffer.get_tag_table().unwrap().lookup("mismatch").unwrap();
+        assert!(source_buffer.get_iter_at_offset(47).has_tag(&mismatch));
+        assert!(!source_buffer.get_iter_at_offset(46).has_tag(&mismatch));
     }
 
     #[test]
@@ -607,19 +556,19 @@ mod tests {
         let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
         let input: gtk::TextView = builder.get_object("txtInput").unwrap();
         let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-        let tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-        let marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
+        let tape_view = TapeView::from_builder(&builder);
 
         let source_buffer = input.get_buffer().unwrap();
         source_buffer.set_text("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<->>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.");
 
         speed_slider.set_value(0.0);
         
-        start_parsing(&tape_lbls, &marker_lbls, &input, &output, &speed_slider);
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config::default(), &ExecutionController::new());
         
         let out_buffer = output.get_buffer().unwrap();
 
-        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "Error: Loop without ending at index 8")
+        let out_text = String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap());
+        assert!(out_text.starts_with("Error: Loop without ending at line 1, column 9\n"));
     }
 
     #[test]
@@ -633,15 +582,14 @@ mod tests {
         let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
         let input: gtk::TextView = builder.get_object("txtInput").unwrap();
         let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-        let tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-        let marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
+        let tape_view = TapeView::from_builder(&builder);
 
         let source_buffer = input.get_buffer().unwrap();
         source_buffer.set_text("-");
 
         speed_slider.set_value(0.0);
         
-        start_parsing(&tape_lbls, &marker_lbls, &input, &output, &speed_slider);
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config::default(), &ExecutionController::new());
         
         let out_buffer = output.get_buffer().unwrap();
 
@@ -659,15 +607,14 @@ mod tests {
         let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
         let input: gtk::TextView = builder.get_object("txtInput").unwrap();
         let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-        let tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-        let marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
+        let tape_view = TapeView::from_builder(&builder);
 
         let source_buffer = input.get_buffer().unwrap();
         source_buffer.set_text("++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++");
 
         speed_slider.set_value(0.0);
         
-        start_parsing(&tape_lbls, &marker_lbls, &input, &output, &speed_slider);
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config::default(), &ExecutionController::new());
         
         let out_buffer = output.get_buffer().unwrap();
 
@@ -685,15 +632,14 @@ mod tests {
         let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
         let input: gtk::TextView = builder.get_object("txtInput").unwrap();
         let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-        let tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-        let marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
+        let tape_view = TapeView::from_builder(&builder);
 
         let source_buffer = input.get_buffer().unwrap();
         source_buffer.set_text("<");
 
         speed_slider.set_value(0.0);
         
-        start_parsing(&tape_lbls, &marker_lbls, &input, &output, &speed_slider);
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config::default(), &ExecutionController::new());
         
         let out_buffer = output.get_buffer().unwrap();
 
@@ -711,15 +657,14 @@ mod tests {
         let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
         let input: gtk::TextView = builder.get_object("txtInput").unwrap();
         let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-        let tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-        let marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
+        let tape_view = TapeView::from_builder(&builder);
 
         let source_buffer = input.get_buffer().unwrap();
         source_buffer.set_text(">>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>");
 
         speed_slider.set_value(0.0);
         
-        start_parsing(&tape_lbls, &marker_lbls, &input, &output, &speed_slider);
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config{tape_length: 32, ..Config::default()}, &ExecutionController::new());
         
         let out_buffer = output.get_buffer().unwrap();
 
@@ -727,7 +672,7 @@ mod tests {
     }
 
     #[test]
-    fn reset() {
+    fn long_tape() {
         if gtk::init().is_err() {
             println!("Failed to initialize GTK.");
             return;
@@ -737,121 +682,203 @@ mod tests {
         let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
         let input: gtk::TextView = builder.get_object("txtInput").unwrap();
         let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-        let tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-        let marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-
-        let tape_lbls_copy = tape_lbls.clone();
-        let marker_lbls_copy = marker_lbls.clone();
-        let input_copy = input.clone();
-        let output_copy = output.clone();    
+        let tape_view = TapeView::from_builder(&builder);
 
         let source_buffer = input.get_buffer().unwrap();
-        source_buffer.set_text("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.");
+        source_buffer.set_text(&format!("{}++++++++[<++++++++>-]<+.", ">".repeat(100)));
 
         speed_slider.set_value(0.0);
         
-        start_parsing(&tape_lbls, &marker_lbls, &input, &output, &speed_slider);
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config::default(), &ExecutionController::new());
         
         let out_buffer = output.get_buffer().unwrap();
-        let in_buffer = input.get_buffer().unwrap();
 
-        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "Hello World!\n");
+        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "A")
+    }
+
+    #[test]
+    fn tape_grows_left() {
+        if gtk::init().is_err() {
+            println!("Failed to initialize GTK.");
+            return;
+        }
+        let glade_src = include_str!("../GUI.glade");
+        let builder = gtk::Builder::from_string(glade_src);
+        let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
+        let input: gtk::TextView = builder.get_object("txtInput").unwrap();
+        let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
+        let tape_view = TapeView::from_builder(&builder);
 
-        reset_app(&tape_lbls_copy, &marker_lbls_copy, &input_copy, &output_copy);
+        let source_buffer = input.get_buffer().unwrap();
+        source_buffer.set_text("<<<++++++++[<++++++++>-]<+.");
 
-        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "");
+        speed_slider.set_value(0.0);
         
-        assert_eq!(String::from(in_buffer.get_text(&in_buffer.get_start_iter(), &in_buffer.get_end_iter(), false).unwrap()), "");
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config{growth: TapeGrowth::Both, ..Config::default()}, &ExecutionController::new());
+        
+        let out_buffer = output.get_buffer().unwrap();
+
+        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "A")
     }
 
-}
+    #[test]
+    fn open_file() {
+        if gtk::init().is_err() {
+            println!("Failed to initialize GTK.");
+            return;
+        }
+        let glade_src = include_str!("../GUI.glade");
+        let builder = gtk::Builder::from_string(glade_src);
+        let window: gtk::Window = builder.get_object("window").unwrap();
+        let input: gtk::TextView = builder.get_object("txtInput").unwrap();
+        let source_buffer = input.get_buffer().unwrap();
+        let document = Document::new(&window, &source_buffer);
 
-//----------------------------------------------------------------TESTS END HERE-------------------------------------------------------------
+        let path = env::temp_dir().join("open_file_test.bf");
+        std::fs::write(&path, "+[-]").unwrap();
 
+        assert!(document.load(&path));
+        assert_eq!(String::from(source_buffer.get_text(&source_buffer.get_start_iter(), &source_buffer.get_end_iter(), false).unwrap()), "+[-]");
+        assert!(!source_buffer.get_modified());
+        assert_eq!(document.path(), Some(path.clone()));
+        assert_eq!(String::from(window.get_title().unwrap()), "open_file_test.bf - Brainfuck Visualizer");
 
-fn main() {
+        source_buffer.set_text("+");
+        assert_eq!(String::from(window.get_title().unwrap()), "*open_file_test.bf - Brainfuck Visualizer");
 
-    if gtk::init().is_err() {
-        println!("Failed to initialize GTK.");
-        return;
+        std::fs::remove_file(&path).unwrap();
     }
 
-    let glade_src = include_str!("../GUI.glade");
-    let builder = gtk::Builder::from_string(glade_src);
-    let window: gtk::Window = builder.get_object("window").unwrap();
-    let start_button: gtk::Button= builder.get_object("btnStart").unwrap();
-    let pause_button: gtk::Button = builder.get_object("btnPause").unwrap();
-    let step_button: gtk::Button = builder.get_object("btnStep").unwrap();
-    let reset_button: gtk::Button = builder.get_object("btnReset").unwrap();
-    let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
-    let input: gtk::TextView = builder.get_object("txtInput").unwrap();
-    let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
-    let mut tape_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
-    let mut marker_lbls: Vec<gtk::Label> = vec![gtk::Label::new(None); 32];
+    #[test]
+    fn batch_input() {
+        if gtk::init().is_err() {
+            println!("Failed to initialize GTK.");
+            return;
+        }
+        let glade_src = include_str!("../GUI.glade");
+        let builder = gtk::Builder::from_string(glade_src);
+        let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
+        let input: gtk::TextView = builder.get_object("txtInput").unwrap();
+        let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
+        let program_input = ProgramInput::from_builder(&builder);
+        let tape_view = TapeView::from_builder(&builder);
 
-    for i in 0..32 {
-        tape_lbls[i] = builder.get_object(("lbl".to_owned() + i.to_string().as_str()).as_str()).unwrap();
-        marker_lbls[i] = builder.get_object(("lblMarker".to_owned() + i.to_string().as_str()).as_str()).unwrap();
-    }
+        let source_buffer = input.get_buffer().unwrap();
+        source_buffer.set_text(",.,.,.");
+        let stdin: gtk::TextView = builder.get_object("txtStdin").unwrap();
+        stdin.get_buffer().unwrap().set_text("abc");
 
-    window.set_title("Brainfuck Visualizer");
+        speed_slider.set_value(0.0);
+        
+        start_parsing(&tape_view, &input, &output, &program_input, &speed_slider, &Config::default(), &ExecutionController::new());
+        
+        let out_buffer = output.get_buffer().unwrap();
 
-    window.show_all();
+        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "abc")
+    }
 
-    let tape_lbls_copy = tape_lbls.clone();
-    let marker_lbls_copy = marker_lbls.clone();
-    let input_copy = input.clone();
-    let output_copy = output.clone();
+    #[test]
+    fn breakpoints() {
+        if gtk::init().is_err() {
+            println!("Failed to initialize GTK.");
+            return;
+        }
+        let glade_src = include_str!("../GUI.glade");
+        let builder = gtk::Builder::from_string(glade_src);
+        let input: gtk::TextView = builder.get_object("txtInput").unwrap();
+        let breakpoints = Breakpoints::from_builder(&builder);
 
-    let tape_lbls_copy_2 = tape_lbls.clone();
-    let marker_lbls_copy_2 = marker_lbls.clone();
-    let input_copy_2 = input.clone();
-    let output_copy_2 = output.clone();
+        let source_buffer = input.get_buffer().unwrap();
+        source_buffer.set_text("+#\ncomment -\n\n.");
+        assert_eq!(breakpoints.offsets(), vec![1]);
 
-    start_button.connect_clicked(move |but| {
+        breakpoints.toggle_line(1);
+        breakpoints.toggle_line(2);
+        assert_eq!(breakpoints.offsets(), vec![1, 11]);
 
-        but.set_sensitive(false);
-        start_parsing(&tape_lbls_copy, &marker_lbls_copy, &input_copy, &output_copy, &speed_slider);
-        but.set_sensitive(true);
+        //breakpoints move with the text before them
+        source_buffer.insert(&mut source_buffer.get_start_iter(), "++");
+        assert_eq!(breakpoints.offsets(), vec![3, 13]);
 
-    });
+        //removing the breakpoint of a # removes the # too
+        breakpoints.toggle_line(0);
+        assert_eq!(breakpoints.offsets(), vec![12]);
+        assert_eq!(String::from(source_buffer.get_text(&source_buffer.get_start_iter(), &source_buffer.get_end_iter(), false).unwrap()), "+++\ncomment -\n\n.");
+    }
 
-    reset_button.connect_clicked(move |_|{
+    #[test]
+    fn watches() {
+        if gtk::init().is_err() {
+            println!("Failed to initialize GTK.");
+            return;
+        }
+        let glade_src = include_str!("../GUI.glade");
+        let builder = gtk::Builder::from_string(glade_src);
+        let watches = Watches::from_builder(&builder);
 
-        input_copy_2.set_editable(true);
-        input_copy_2.set_cursor_visible(true);
-        
-        reset_app(&tape_lbls_copy_2, &marker_lbls_copy_2, &input_copy_2, &output_copy_2);
+        assert!(watches.add("cell[3] == 10").is_ok());
+        assert!(watches.add("change cell[2]").is_ok());
+        assert!(watches.add("cell[3] = 10").is_err());
+        assert_eq!(watches.enabled(), vec!["cell[3] == 10".parse().unwrap(), bf::Watch::Change(2)]);
+    }
 
-    });   
+    #[test]
+    fn timeline() {
+        if gtk::init().is_err() {
+            println!("Failed to initialize GTK.");
+            return;
+        }
+        let glade_src = include_str!("../GUI.glade");
+        let builder = gtk::Builder::from_string(glade_src);
+        let timeline = Timeline::from_builder(&builder);
+        let slider: gtk::Scale = builder.get_object("sliderTimeline").unwrap();
 
-    pause_button.connect_clicked(move |_|{
+        let controller = ExecutionController::new();
+        timeline.refresh(&controller);
+        assert_eq!(slider.get_value(), 0.0);
 
-        if PAUSE.load(Ordering::Relaxed) == false{
-            PAUSE.store(true, Ordering::Relaxed);
-        }
-        else{
-            PAUSE.store(false, Ordering::Relaxed);
+        timeline.clear();
+        assert_eq!(slider.get_adjustment().get_upper(), 0.0);
+    }
+
+    #[test]
+    fn reset() {
+        if gtk::init().is_err() {
+            println!("Failed to initialize GTK.");
+            return;
         }
+        let glade_src = include_str!("../GUI.glade");
+        let builder = gtk::Builder::from_string(glade_src);
+        let speed_slider: gtk::Scale = builder.get_object("sliderSpeed").unwrap();
+        let input: gtk::TextView = builder.get_object("txtInput").unwrap();
+        let output: gtk::TextView = builder.get_object("txtOutput").unwrap();
+        let tape_view = TapeView::from_builder(&builder);
 
-    });
+        let tape_view_copy = tape_view.clone();
+        let input_copy = input.clone();
+        let output_copy = output.clone();    
 
-    step_button.connect_clicked(move |_|{
+        let source_buffer = input.get_buffer().unwrap();
+        source_buffer.set_text("++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.");
 
-        PAUSE.store(false, Ordering::Relaxed);
-        let delay = DELAY.load(Ordering::Relaxed);
+        speed_slider.set_value(0.0);
 
-        DELAY.store(10, Ordering::Relaxed);
-        thread::sleep(time::Duration::from_millis(5));
-        DELAY.store(delay, Ordering::Relaxed);
-        PAUSE.store(true, Ordering::Relaxed);
+        let controller = ExecutionController::new();
+        
+        start_parsing(&tape_view, &input, &output, &ProgramInput::from_builder(&builder), &speed_slider, &Config::default(), &controller);
+        
+        let out_buffer = output.get_buffer().unwrap();
+        let in_buffer = input.get_buffer().unwrap();
 
-    });
+        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "Hello World!\n");
 
-    window.connect_destroy(|_| {
-        process::exit(0);
-    });
+        reset_app(&tape_view_copy, &input_copy, &output_copy, &controller);
 
-    gtk::main();
+        assert_eq!(String::from(out_buffer.get_text(&out_buffer.get_start_iter(), &out_buffer.get_end_iter(), false).unwrap()), "");
+        
+        assert_eq!(String::from(in_buffer.get_text(&in_buffer.get_start_iter(), &in_buffer.get_end_iter(), false).unwrap()), "");
+    }
 
-}
\ No newline at end of file
+}
+
+//----------------------------------------------------------------TESTS END HERE-------------------------------------------------------------
@@ -0,0 +1,55 @@
+use gtk::prelude::*;
+
+use std::collections::VecDeque;
+
+/// The pane where the input of the program is typed in before it runs,
+/// either as text or as hex bytes.
+#[derive(Clone)]
+pub struct ProgramInput {
+    view: gtk::TextView,
+    format: gtk::ComboBoxText,
+}
+
+impl ProgramInput {
+    pub fn from_builder(builder: &gtk::Builder) -> ProgramInput {
+        ProgramInput {
+            view: builder.get_object("txtStdin").unwrap(),
+            format: builder.get_object("comboStdinFormat").unwrap(),
+        }
+    }
+
+    /// The bytes `,` will read, in order.
+    pub fn bytes(&self) -> Result<VecDeque<u8>, String> {
+        let buffer = self.view.get_buffer().unwrap();
+        let text = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false).unwrap();
+
+        if self.format.get_active_id().as_ref().map(|id| id.as_str()) == Some("hex") {
+            parse_hex(text.as_str())
+        }
+        else {
+            Ok(text.as_str().bytes().collect())
+        }
+    }
+
+    /// Greys out the pane while a program is running.
+    pub fn set_sensitive(&self, sensitive: bool) {
+        self.view.set_editable(sensitive);
+        self.format.set_sensitive(sensitive);
+    }
+}
+
+//reads bytes written as pairs of hex digits, whitespace between them is optional
+fn parse_hex(text: &str) -> Result<VecDeque<u8>, String> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+
+    if digits.len() % 2 != 0 {
+        return Err(String::from("Hex input must have two digits per byte"));
+    }
+
+    digits.chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("Invalid hex byte '{}' in input", pair))
+        })
+        .collect()
+}
@@ -0,0 +1,61 @@
+use gtk::prelude::*;
+
+use bf::{Config, Optimizations};
+
+/// The widgets in the settings column, read into a `Config` on every start.
+#[derive(Clone)]
+pub struct Settings {
+    tape_length: gtk::SpinButton,
+    growth: gtk::ComboBoxText,
+    overflow: gtk::ComboBoxText,
+    cell_type: gtk::ComboBoxText,
+    eof: gtk::ComboBoxText,
+    fold: gtk::CheckButton,
+    idioms: gtk::CheckButton,
+}
+
+impl Settings {
+    pub fn from_builder(builder: &gtk::Builder) -> Settings {
+        Settings {
+            tape_length: builder.get_object("spinTapeLength").unwrap(),
+            growth: builder.get_object("comboTapeGrowth").unwrap(),
+            overflow: builder.get_object("comboOverflow").unwrap(),
+            cell_type: builder.get_object("comboCellType").unwrap(),
+            eof: builder.get_object("comboEof").unwrap(),
+            fold: builder.get_object("chkFold").unwrap(),
+            idioms: builder.get_object("chkIdioms").unwrap(),
+        }
+    }
+
+    //the combo box ids are the names the bf crate parses
+    pub fn config(&self) -> Config {
+        let defaults = Config::default();
+
+        Config {
+            tape_length: self.tape_length.get_value_as_int() as usize,
+            growth: parse_id(&self.growth).unwrap_or(defaults.growth),
+            overflow: parse_id(&self.overflow).unwrap_or(defaults.overflow),
+            cell_type: parse_id(&self.cell_type).unwrap_or(defaults.cell_type),
+            eof: parse_id(&self.eof).unwrap_or(defaults.eof),
+            optimizations: Optimizations {
+                fold: self.fold.get_active(),
+                idioms: self.idioms.get_active(),
+            },
+        }
+    }
+
+    /// Greys out the settings while a program is running.
+    pub fn set_sensitive(&self, sensitive: bool) {
+        self.tape_length.set_sensitive(sensitive);
+        self.growth.set_sensitive(sensitive);
+        self.overflow.set_sensitive(sensitive);
+        self.cell_type.set_sensitive(sensitive);
+        self.eof.set_sensitive(sensitive);
+        self.fold.set_sensitive(sensitive);
+        self.idioms.set_sensitive(sensitive);
+    }
+}
+
+fn parse_id<T: std::str::FromStr>(combo: &gtk::ComboBoxText) -> Option<T> {
+    combo.get_active_id().and_then(|id| id.as_str().parse().ok())
+}
@@ -0,0 +1,155 @@
+use gtk::prelude::*;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+/// Number of cells shown at once, one per `lblN`/`lblMarkerN` pair.
+pub const VISIBLE_CELLS: usize = 32;
+
+/// A window of `VISIBLE_CELLS` cells over the tape, moved with the scrollbar
+/// under it. It keeps its own copy of the tape, so cells that scrolled out of
+/// view can be drawn again, and grows it like a growing tape would.
+#[derive(Clone)]
+pub struct TapeView {
+    tape_lbls: Vec<gtk::Label>,
+    marker_lbls: Vec<gtk::Label>,
+    range_lbl: gtk::Label,
+    step_lbl: gtk::Label,
+    scrollbar: gtk::Scrollbar,
+    cells: Rc<RefCell<Vec<i64>>>,
+    //index of cells[0], negative once the tape grew to the left
+    first: Rc<Cell<isize>>,
+    pointer: Rc<Cell<isize>>,
+}
+
+impl TapeView {
+    pub fn from_builder(builder: &gtk::Builder) -> TapeView {
+        let mut tape_lbls = Vec::with_capacity(VISIBLE_CELLS);
+        let mut marker_lbls = Vec::with_capacity(VISIBLE_CELLS);
+
+        for i in 0..VISIBLE_CELLS {
+            tape_lbls.push(builder.get_object(&format!("lbl{}", i)).unwrap());
+            marker_lbls.push(builder.get_object(&format!("lblMarker{}", i)).unwrap());
+        }
+
+        let view = TapeView {
+            tape_lbls,
+            marker_lbls,
+            range_lbl: builder.get_object("lblTapeRange").unwrap(),
+            step_lbl: builder.get_object("lblStep").unwrap(),
+            scrollbar: builder.get_object("scrollTape").unwrap(),
+            cells: Rc::new(RefCell::new(vec![0; VISIBLE_CELLS])),
+            first: Rc::new(Cell::new(0)),
+            pointer: Rc::new(Cell::new(0)),
+        };
+
+        let view_copy = view.clone();
+        view.adjustment().connect_value_changed(move |_| {
+            view_copy.render();
+        });
+
+        view.render();
+        view
+    }
+
+    fn adjustment(&self) -> gtk::Adjustment {
+        self.scrollbar.get_adjustment()
+    }
+
+    //index of the leftmost visible cell
+    fn offset(&self) -> isize {
+        self.adjustment().get_value() as isize
+    }
+
+    //makes the scrollbar cover the whole copy of the tape
+    fn update_range(&self) {
+        let length = self.cells.borrow().len();
+        let adjustment = self.adjustment();
+
+        adjustment.set_lower(self.first.get() as f64);
+        adjustment.set_upper((self.first.get() + length as isize) as f64);
+        adjustment.set_page_size(VISIBLE_CELLS.min(length) as f64);
+    }
+
+    /// Empties the tape and resizes it to `length` cells.
+    pub fn clear(&self, length: usize) {
+        *self.cells.borrow_mut() = vec![0; length.max(1)];
+        self.first.set(0);
+        self.pointer.set(0);
+        self.step_lbl.set_text("");
+
+        self.update_range();
+        self.adjustment().set_value(0.0);
+
+        self.render();
+    }
+
+    /// Empties the tape and keeps its size.
+    pub fn reset(&self) {
+        let length = self.cells.borrow().len();
+        self.clear(length);
+    }
+
+    /// Stores `content` in cell `index`, moves the pointer there and scrolls
+    /// to it if it is out of view.
+    pub fn update(&self, index: isize, content: i64) {
+        {
+            let mut cells = self.cells.borrow_mut();
+            let first = self.first.get();
+
+            if index < first {
+                cells.splice(0..0, std::iter::repeat(0).take((first - index) as usize));
+                self.first.set(index);
+            }
+            else if index >= first + cells.len() as isize {
+                cells.resize((index - first) as usize + 1, 0);
+            }
+
+            cells[(index - self.first.get()) as usize] = content;
+        }
+        self.pointer.set(index);
+        self.update_range();
+
+        let offset = self.offset();
+        if index < offset || index >= offset + VISIBLE_CELLS as isize {
+            //setting the value redraws through connect_value_changed
+            self.adjustment().set_value((index - VISIBLE_CELLS as isize / 2) as f64);
+        }
+
+        self.render();
+    }
+
+    /// Shows what the last step did when it ran a whole loop at once, like
+    /// "clear cell 3". An empty text hides it.
+    pub fn set_step(&self, text: &str) {
+        self.step_lbl.set_text(text);
+    }
+
+    fn render(&self) {
+        let cells = self.cells.borrow();
+        let first = self.first.get();
+        let offset = self.offset();
+
+        for i in 0..VISIBLE_CELLS {
+            let index = offset + i as isize;
+            let cell = if index >= first { cells.get((index - first) as usize) } else { None };
+
+            match cell {
+                Some(content) => {
+                    //wide cells do not fit in the label, the tooltip has the whole value
+                    self.tape_lbls[i].set_text(&content.to_string());
+                    self.tape_lbls[i].set_tooltip_text(Some(&format!("Cell {}: {}", index, content)));
+                    self.marker_lbls[i].set_text(if index == self.pointer.get() { "#" } else { "" });
+                },
+                None => {
+                    self.tape_lbls[i].set_text("");
+                    self.tape_lbls[i].set_tooltip_text(None);
+                    self.marker_lbls[i].set_text("");
+                }
+            }
+        }
+
+        let last = (offset + VISIBLE_CELLS as isize).min(first + cells.len() as isize);
+        self.range_lbl.set_text(&format!("Cells {} - {} of {}", offset, last - 1, cells.len()));
+    }
+}
@@ -0,0 +1,62 @@
+use gtk::prelude::*;
+
+use bf::ExecutionController;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// How many instructions a run keeps so the timeline can go back over them.
+pub const HISTORY_LIMIT: usize = 100_000;
+
+/// The slider under the output, `sliderTimeline`, which shows how many
+/// instructions the program can step back. Dragging it back rewinds a paused
+/// program, dragging it forward steps it again.
+#[derive(Clone)]
+pub struct Timeline {
+    scale: gtk::Scale,
+    //the history length shown, so a drag is only overridden when it changes
+    shown: Rc<Cell<usize>>,
+}
+
+impl Timeline {
+    pub fn from_builder(builder: &gtk::Builder) -> Timeline {
+        Timeline {
+            scale: builder.get_object("sliderTimeline").unwrap(),
+            shown: Rc::new(Cell::new(0)),
+        }
+    }
+
+    /// Moves the slider to where `controller` is in its history.
+    pub fn refresh(&self, controller: &ExecutionController) {
+        let length = controller.history_len();
+        if length == self.shown.get() {
+            return;
+        }
+        self.shown.set(length);
+
+        let adjustment = self.scale.get_adjustment();
+        if length as f64 > adjustment.get_upper() {
+            adjustment.set_upper(length as f64);
+        }
+        adjustment.set_value(length as f64);
+    }
+
+    /// Empties the timeline for a new run.
+    pub fn clear(&self) {
+        self.shown.set(0);
+
+        let adjustment = self.scale.get_adjustment();
+        adjustment.set_upper(0.0);
+        adjustment.set_value(0.0);
+    }
+
+    /// Calls `listener` with the point in the history the user moved the
+    /// slider to.
+    pub fn connect_seek<F: Fn(usize) + 'static>(&self, listener: F) {
+        self.scale.connect_change_value(move |scale, _, value| {
+            let upper = scale.get_adjustment().get_upper();
+            listener(value.max(0.0).min(upper).round() as usize);
+            Inhibit(false)
+        });
+    }
+}
@@ -0,0 +1,122 @@
+use gtk::prelude::*;
+
+use bf::Watch;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+//columns of storeWatches
+const ENABLED_COLUMN: u32 = 0;
+const TEXT_COLUMN: u32 = 1;
+
+//called with the watches that are on
+type Listener = Box<dyn Fn(Vec<Watch>)>;
+
+/// The watches of the debugger, conditions like `cell[3] == 10` or
+/// `ptr > 20` and `write cell[N]` or `change cell[N]` for a single cell.
+/// They are typed into `entryWatch` and listed in `treeWatches`, where they
+/// can be turned off or removed.
+#[derive(Clone)]
+pub struct Watches {
+    entry: gtk::Entry,
+    list: gtk::TreeView,
+    store: gtk::ListStore,
+    //in the order of the rows of the list, with whether they are on
+    watches: Rc<RefCell<Vec<(Watch, bool)>>>,
+    listeners: Rc<RefCell<Vec<Listener>>>,
+}
+
+impl Watches {
+    pub fn from_builder(builder: &gtk::Builder) -> Watches {
+        let watches = Watches {
+            entry: builder.get_object("entryWatch").unwrap(),
+            list: builder.get_object("treeWatches").unwrap(),
+            store: builder.get_object("storeWatches").unwrap(),
+            watches: Rc::new(RefCell::new(Vec::new())),
+            listeners: Rc::new(RefCell::new(Vec::new())),
+        };
+        let enabled: gtk::CellRendererToggle = builder.get_object("rendererWatchEnabled").unwrap();
+        let add_button: gtk::Button = builder.get_object("btnAddWatch").unwrap();
+        let remove_button: gtk::Button = builder.get_object("btnRemoveWatch").unwrap();
+
+        let copy = watches.clone();
+        watches.entry.connect_activate(move |_| copy.add_entry());
+
+        let copy = watches.clone();
+        add_button.connect_clicked(move |_| copy.add_entry());
+
+        //the error of the last attempt goes away once the text is edited
+        watches.entry.connect_changed(|entry| {
+            entry.set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, None);
+            entry.set_icon_tooltip_text(gtk::EntryIconPosition::Secondary, None);
+        });
+
+        let copy = watches.clone();
+        enabled.connect_toggled(move |_, path| {
+            if let Some(&row) = path.get_indices().first() {
+                if let Some((_, enabled)) = copy.watches.borrow_mut().get_mut(row as usize) {
+                    *enabled = !*enabled;
+                }
+                copy.update();
+            }
+        });
+
+        let copy = watches.clone();
+        remove_button.connect_clicked(move |_| {
+            let selected = copy.list.get_selection().get_selected()
+                .and_then(|(model, iter)| model.get_path(&iter))
+                .and_then(|path| path.get_indices().first().copied());
+
+            if let Some(row) = selected {
+                copy.watches.borrow_mut().remove(row as usize);
+                copy.update();
+            }
+        });
+
+        watches
+    }
+
+    /// The watches that are on.
+    pub fn enabled(&self) -> Vec<Watch> {
+        self.watches.borrow().iter().filter(|(_, enabled)| *enabled).map(|(watch, _)| *watch).collect()
+    }
+
+    /// Calls `listener` with the watches that are on whenever one is added,
+    /// removed, turned on or off.
+    pub fn connect_changed<F: Fn(Vec<Watch>) + 'static>(&self, listener: F) {
+        self.listeners.borrow_mut().push(Box::new(listener));
+    }
+
+    /// Adds the watch written in `text`, or says what is wrong with it.
+    pub fn add(&self, text: &str) -> Result<(), String> {
+        let watch = text.parse()?;
+
+        self.watches.borrow_mut().push((watch, true));
+        self.update();
+        Ok(())
+    }
+
+    //adds the watch in the entry, which is emptied for the next one
+    fn add_entry(&self) {
+        match self.add(self.entry.get_text().as_str()) {
+            Ok(()) => self.entry.set_text(""),
+            Err(e) => {
+                self.entry.set_icon_from_icon_name(gtk::EntryIconPosition::Secondary, Some("dialog-error"));
+                self.entry.set_icon_tooltip_text(gtk::EntryIconPosition::Secondary, Some(&e));
+            }
+        }
+    }
+
+    //shows the watches in the list and tells the listeners
+    fn update(&self) {
+        self.store.clear();
+        for (watch, enabled) in self.watches.borrow().iter() {
+            self.store.insert_with_values(None, &[ENABLED_COLUMN, TEXT_COLUMN], &[enabled, &watch.to_string()]);
+        }
+
+        let enabled = self.enabled();
+        for listener in self.listeners.borrow().iter() {
+            listener(enabled.clone());
+        }
+    }
+}